@@ -28,18 +28,29 @@ fn start_message(
                 "yurumoji.ttf",
                 "yinghuayunduoxiaohuzi.ttf",
                 "NotoSansJP-Black.ttf",
-            ].iter().map(|s| String::from("../../text_test/assets/fonts/".to_owned() + s)).collect(),
+            ].iter().map(|s| "../../text_test/assets/fonts/".to_owned() + s).collect(),
             background_path: "../../text_test/assets/2d_picture/ui/messageframe/material/messageframe_non_line/message_001.png".to_string(),
             position: Vec2::new(0., -200.),
             feeding: FeedingStyle::Scroll { size: 0, sec: 0.5 },
             script_path: "scripts/test.bms".to_string(),
             main_box_origin: Vec2::new(-540.0, 70.0),
             main_box_size: Vec2::new(1060.0, 140.0),
+            // theme_path: Some("themes/default.mwtheme.ron".to_string()),
+            // nine_slice: Some(NineSlice { left: 32., right: 32., top: 32., bottom: 32., edge: SliceMode::Stretch, center: SliceMode::Tile }),
+            // background_padding: Vec2::new(40., 30.),
+            // bubble: Some(SpeechBubble { target: fox_entity, camera: None, offset: Vec3::new(0., 120., 0.), gap: 40., margin: 20., tail_path: "texture/ui/bubble_tail.png".to_string(), tail_width: 32. }),
+            // popup: PopupType::Slide { sec: 0.5, from: ScreenEdge::Bottom, easing: Easing::OutBack },
             // main_alignment: TextAlignment::Center,
+            // main_justify: true,
+            // main_vertical_alignment: VerticalAlignment::Middle,
             // writing:WritingStyle::Wipe{ sec: 0.7 },
             // writing:WritingStyle::Put,
             // typing_timing: TypingTiming::ByLine { sec: 1.5 },
-            // typing_timing: TypingTiming::ByPage,
+            // typing_timing: TypingTiming::ByPage { sec: 0.5 },
+            // overflow: OverflowPolicy::ShrinkToFit,
+            // outline: Some(TextOutline { color: Color::BLACK, thickness: 2. }),
+            // shadow: Some(TextShadow { color: Color::BLACK.with_a(0.5), offset: Vec2::new(3., -3.) }),
+            // punctuation_pauses: [('。', 0.3), ('、', 0.15), ('…', 0.5)].into(),
             wait_breaker: WaitBrakerStyle::Input {icon_entity: Some(waiting_sprite.single()), is_icon_moving_to_last: true},
             ..default()
         });
@@ -110,7 +121,7 @@ fn waiting_sprite_setup(
     commands.spawn((
         SpriteSheetBundle {
             texture_atlas: texture_atlas_handle,
-            sprite: sprite,
+            sprite,
             transform: Transform::from_scale(Vec3::splat(1.5)),
             ..default()
         },
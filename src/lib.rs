@@ -4,3 +4,4 @@ mod read_script;
 mod utility;
 
 pub use message_window::window_controller::*;
+pub use utility::GlyphCoverage;
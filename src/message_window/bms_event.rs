@@ -8,15 +8,211 @@ pub struct FontSizeChange {
     pub size: f32,
 }
 
+#[allow(clippy::type_complexity)]
 pub fn change_font_size(
     mut events: EventReader<BMSEvent>,
-    mut tb_query: Query<&mut TypeTextConfig, (With<Current>, With<TextBox>)>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
 ) {
     for event_wrapper in events.iter() {
         if let Some(FontSizeChange { size: s }) = event_wrapper.get_opt::<FontSizeChange>() {
-            if let Ok(mut config) = tb_query.get_single_mut() {
+            for (mut config, _) in tb_query
+                .iter_mut()
+                .filter(|(_, p)| event_wrapper.is_for(p.get()))
+            {
+                config.push_style();
                 config.text_style.font_size = s;
             }
         }
     }
 }
+
+#[derive(Reflect, Default, Debug)]
+pub struct FontColorChange {
+    pub color: Color,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_font_color(
+    mut events: EventReader<BMSEvent>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(FontColorChange { color: c }) = event_wrapper.get_opt::<FontColorChange>() {
+            for (mut config, _) in tb_query
+                .iter_mut()
+                .filter(|(_, p)| event_wrapper.is_for(p.get()))
+            {
+                config.push_style();
+                config.text_style.color = c;
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct FontChange {
+    pub name: String,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_font(
+    mut events: EventReader<BMSEvent>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
+    font_sets: Res<FontSets>,
+    asset_server: Res<AssetServer>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(FontChange { name: n }) = event_wrapper.get_opt::<FontChange>() {
+            for (mut config, _) in tb_query
+                .iter_mut()
+                .filter(|(_, p)| event_wrapper.is_for(p.get()))
+            {
+                config.push_style();
+                match font_sets.fonts.get(&n) {
+                    Some(paths) => {
+                        config.fonts = paths.iter().map(|p| asset_server.load(p)).collect()
+                    }
+                    None => warn!("font set \"{n}\" is not registered"),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct PushWritingStyle {
+    pub writing: WritingStyle,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_writing_style(
+    mut events: EventReader<BMSEvent>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(PushWritingStyle { writing: w }) = event_wrapper.get_opt::<PushWritingStyle>() {
+            for (mut config, _) in tb_query
+                .iter_mut()
+                .filter(|(_, p)| event_wrapper.is_for(p.get()))
+            {
+                config.push_style();
+                config.writing = w;
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct PushTextEffect {
+    pub effect: TextEffect,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_text_effect(
+    mut events: EventReader<BMSEvent>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(PushTextEffect { effect: e }) = event_wrapper.get_opt::<PushTextEffect>() {
+            for (mut config, _) in tb_query
+                .iter_mut()
+                .filter(|(_, p)| event_wrapper.is_for(p.get()))
+            {
+                config.push_style();
+                config.effects.push(e);
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct ChangeVoice {
+    pub name: String,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_voice(
+    mut events: EventReader<BMSEvent>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
+    voice_sets: Res<VoiceSets>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(ChangeVoice { name: n }) = event_wrapper.get_opt::<ChangeVoice>() {
+            for (mut config, _) in tb_query
+                .iter_mut()
+                .filter(|(_, p)| event_wrapper.is_for(p.get()))
+            {
+                config.push_style();
+                config.voice = voice_sets.voices.get(&n).cloned();
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct ChangeSpeaker {
+    pub name: String,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_speaker(
+    mut events: EventReader<BMSEvent>,
+    mut mw_query: Query<(Entity, &mut Speaker), (With<Current>, With<MessageWindow>)>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(ChangeSpeaker { name: n }) = event_wrapper.get_opt::<ChangeSpeaker>() {
+            for (mw_entity, mut speaker) in &mut mw_query {
+                if event_wrapper.is_for(mw_entity) {
+                    speaker.name = n.clone();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct SetVariable {
+    pub name: String,
+    pub value: String,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn set_variable(
+    mut events: EventReader<BMSEvent>,
+    mut mw_query: Query<(Entity, &mut ScriptVariables), (With<Current>, With<MessageWindow>)>,
+) {
+    for event_wrapper in events.iter() {
+        if let Some(SetVariable { name: n, value: v }) = event_wrapper.get_opt::<SetVariable>() {
+            for (mw_entity, mut variables) in &mut mw_query {
+                if event_wrapper.is_for(mw_entity) {
+                    variables.values.insert(n.clone(), v.clone());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct ResetTextStyle;
+
+#[derive(Reflect, Default, Debug)]
+pub struct PopTextStyle;
+
+#[allow(clippy::type_complexity)]
+pub fn reset_or_pop_text_style(
+    mut events: EventReader<BMSEvent>,
+    mut tb_query: Query<(&mut TypeTextConfig, &Parent), (With<Current>, With<TextBox>)>,
+) {
+    for event_wrapper in events.iter() {
+        for (mut config, _) in tb_query
+            .iter_mut()
+            .filter(|(_, p)| event_wrapper.is_for(p.get()))
+        {
+            if event_wrapper.get_opt::<ResetTextStyle>().is_some() {
+                config.reset_style();
+            } else if event_wrapper.get_opt::<PopTextStyle>().is_some() {
+                config.pop_style();
+            }
+        }
+    }
+}
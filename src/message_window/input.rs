@@ -1,3 +1,6 @@
+use crate::message_window::window_controller::backlog::BacklogViewer;
+use crate::message_window::window_controller::pause::Paused;
+use crate::message_window::window_controller::LastInputDevice;
 use crate::message_window::MessageWindowCamera;
 use crate::read_script::*;
 use bevy::prelude::*;
@@ -12,12 +15,34 @@ pub struct WaitInputGo {
     pub area: Rect,
 }
 
-// ToDo: 長押しで連続スキップできるようにしときたい
+pub fn track_input_device(
+    mut last_device: ResMut<LastInputDevice>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    let device = if gamepad_buttons.get_just_pressed().next().is_some() {
+        LastInputDevice::Gamepad
+    } else if keys.get_just_pressed().next().is_some() {
+        LastInputDevice::Keyboard
+    } else if mouse_buttons.get_just_pressed().next().is_some() {
+        LastInputDevice::Mouse
+    } else if touches.iter_just_pressed().next().is_some() {
+        LastInputDevice::Touch
+    } else {
+        return;
+    };
+    last_device.set_if_neq(device);
+}
+
+// 長押しでの連続スキップはplay_mode::skip_forwardで扱う。
 #[allow(clippy::nonminimal_bool)]
 pub fn go_selected(
     mut commands: Commands,
-    target_query: Query<(Entity, &WaitInputGo)>,
+    target_query: Query<(Entity, &WaitInputGo), Without<Paused>>,
     selected_query: Query<Entity, With<Selected>>,
+    viewer_query: Query<(), With<BacklogViewer>>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     camera_query: Query<(&Camera, &GlobalTransform), With<MessageWindowCamera>>,
     keys: Res<Input<KeyCode>>,
@@ -28,6 +53,9 @@ pub fn go_selected(
     gamepads: Res<Gamepads>,
     type_registry: Res<AppTypeRegistry>,
 ) {
+    if !viewer_query.is_empty() {
+        return;
+    }
     for (target_entity, wig) in &target_query {
         let pointed_opt = camera_query
             .get_single()
@@ -47,7 +75,7 @@ pub fn go_selected(
         let is_selected = selected_query
             .get_single()
             .is_ok_and(|e| e == target_entity);
-        let is_pointed = pointed_opt.is_some_and(|x| (wig.area.contains(x)));
+        let is_pointed = pointed_opt.is_some_and(|x| wig.area.contains(x));
         let gamepad_go_button = gamepads.iter().next().map(|x| GamepadButton {
             gamepad: x,
             button_type: GamepadButtonType::South,
@@ -58,7 +86,10 @@ pub fn go_selected(
             || touched_position_list.any(|t| wig.area.contains(t))
         {
             if let Ok(ref_value) = read_ron(&type_registry, wig.ron.clone()) {
-                bms_event.send(BMSEvent { value: ref_value });
+                bms_event.send(BMSEvent {
+                    value: ref_value,
+                    window: None,
+                });
             }
             commands.entity(target_entity).remove::<WaitInputGo>();
         }
@@ -1,5 +1,6 @@
 use crate::read_script::*;
-use bevy::prelude::*;
+use crate::utility::*;
+use bevy::{prelude::*, text::update_text2d_layout, transform::TransformSystem};
 
 mod bms_event;
 mod input;
@@ -11,10 +12,20 @@ use bms_event::*;
 use input::*;
 use setup::*;
 use text_conroller::feed_animation::*;
+use text_conroller::text_decorations::*;
+use text_conroller::text_effects::*;
 use text_conroller::typing_animations::*;
+use text_conroller::typing_sound::*;
 use text_conroller::*;
+use window_controller::backlog::*;
+use window_controller::bubble::*;
+use window_controller::nine_slice::*;
+use window_controller::pause::*;
+use window_controller::play_mode::*;
 use window_controller::popup::*;
+use window_controller::save::*;
 use window_controller::sinkdown::*;
+use window_controller::theme::*;
 use window_controller::waiting::*;
 use window_controller::*;
 
@@ -43,33 +54,142 @@ impl Plugin for MessageWindowPlugin {
     fn build(&self, app: &mut App) {
         app.add_asset::<BMWScript>()
             .init_asset_loader::<BMWScriptLoader>()
+            .add_asset::<WindowTheme>()
+            .init_asset_loader::<WindowThemeLoader>()
             .insert_resource(SetupConfig {
                 render_layer: self.layer_num,
                 render_order: self.render_order,
             })
+            .init_resource::<FontSets>()
+            .init_resource::<VoiceSets>()
+            .init_resource::<Backlog>()
+            .init_resource::<BacklogConfig>()
+            .init_resource::<PlayModeConfig>()
+            .init_resource::<MessagePause>()
+            .init_resource::<GlyphCoverage>()
+            .init_resource::<ButtonIconSets>()
+            .init_resource::<LastInputDevice>()
             .register_type::<FontSizeChange>()
+            .register_type::<FontColorChange>()
+            .register_type::<FontChange>()
+            .register_type::<ResetTextStyle>()
+            .register_type::<PopTextStyle>()
+            .register_type::<PushWritingStyle>()
+            .register_type::<WritingStyle>()
+            .register_type::<PushTextEffect>()
+            .register_type::<TextEffect>()
+            .register_type::<ChangeVoice>()
+            .register_type::<TypingVoice>()
+            .register_type::<TextOutline>()
+            .register_type::<TextShadow>()
+            .register_type::<ChangeSpeaker>()
+            .register_type::<SetVariable>()
+            .register_type::<OpenBacklog>()
+            .register_type::<CloseBacklog>()
             .register_type::<SinkDownWindow>()
             .register_type::<Option<Entity>>()
             .register_type::<InputForFeeding>()
             .register_type::<InputForSkipping>()
             .register_type::<GoSinking>()
             .register_type::<SinkDownType>()
+            .register_type::<PopupType>()
+            .register_type::<ScreenEdge>()
+            .register_type::<Easing>()
+            .register_type::<SetAutoMode>()
+            .register_type::<SetSkipMode>()
             .register_type::<SimpleWait>()
+            .register_type::<Order>()
+            .register_type::<Vec<Order>>()
+            .register_type::<WindowState>()
+            .register_type::<DialogueSave>()
+            .register_type::<SavedTextStyle>()
+            .register_type::<Vec<SavedTextStyle>>()
+            .register_type::<SavedWait>()
+            .register_type::<std::collections::HashMap<String, String>>()
+            .register_type::<Vec<TextEffect>>()
+            .register_type::<Option<TypingVoice>>()
             .register_type::<BreakWait>()
+            .register_type::<WindowTheme>()
+            .register_type::<ChangeTheme>()
+            .register_type::<NineSlice>()
+            .register_type::<SliceMode>()
+            .register_type::<TypingTiming>()
+            .register_type::<FeedingStyle>()
+            .register_type::<FeedDirection>()
+            .register_type::<WaitBrakerStyle>()
+            .register_type::<Vec<String>>()
+            .register_type::<Option<Vec<String>>>()
+            .register_type::<Option<f32>>()
+            .register_type::<Option<Color>>()
+            .register_type::<Option<String>>()
+            .register_type::<Option<NineSlice>>()
+            .register_type::<Option<Vec2>>()
+            .register_type::<Option<PopupType>>()
+            .register_type::<Option<SinkDownType>>()
+            .register_type::<Option<TypingTiming>>()
+            .register_type::<Option<WritingStyle>>()
+            .register_type::<Option<FeedingStyle>>()
+            .register_type::<Option<WaitBrakerStyle>>()
             .add_event::<OpenWindowEvent>()
             .add_event::<FeedWaitingEvent>()
             .add_event::<StartFeedingEvent>()
             .add_event::<GoSinking>()
+            .add_event::<WindowLifecycleEvent>()
+            .add_event::<CloseWindowEvent>()
+            .add_event::<PauseEvent>()
             .add_event::<BMSEvent>()
             .configure_sets(
                 Update,
                 (PhaseSet::Progress, PhaseSet::Setting, PhaseSet::Change).chain(),
             )
             .add_systems(Startup, setup_camera)
+            .add_systems(PreUpdate, reset_text_effects)
+            .add_systems(PreUpdate, update_glyph_coverage)
+            .add_systems(PreUpdate, track_input_device)
+            .add_systems(PreUpdate, (pause_or_resume, propagate_pause).chain())
+            .add_systems(
+                PostUpdate,
+                apply_text_effects.before(TransformSystem::TransformPropagate),
+            )
+            .add_systems(
+                PostUpdate,
+                sync_text_decorations
+                    .after(apply_text_effects)
+                    .before(update_text2d_layout),
+            )
+            .add_systems(PostUpdate, justify_line_layouts.after(update_text2d_layout))
             .add_systems(Update, script_on_load.in_set(PhaseSet::Setting))
             .add_systems(Update, trigger_type_animation.in_set(PhaseSet::Setting))
+            .add_systems(Update, reveal_line_glyphs.in_set(PhaseSet::Setting))
+            .add_systems(
+                Update,
+                play_typing_blips
+                    .in_set(PhaseSet::Setting)
+                    .after(reveal_line_glyphs),
+            )
+            .add_systems(Update, stop_typing_blips.in_set(PhaseSet::Setting))
             .add_systems(Update, setup_feed_starter.in_set(PhaseSet::Setting))
             .add_systems(Update, change_font_size.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_font_color.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_font.in_set(PhaseSet::Setting))
+            .add_systems(Update, reset_or_pop_text_style.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_writing_style.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_text_effect.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_voice.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_speaker.in_set(PhaseSet::Setting))
+            .add_systems(Update, set_variable.in_set(PhaseSet::Setting))
+            .add_systems(Update, resume_restored_wait.in_set(PhaseSet::Setting))
+            .add_systems(Update, change_play_mode.in_set(PhaseSet::Setting))
+            .add_systems(
+                Update,
+                stop_skip_on_choice
+                    .in_set(PhaseSet::Setting)
+                    .after(change_play_mode),
+            )
+            .add_systems(Update, apply_window_themes.in_set(PhaseSet::Setting))
+            .add_systems(Update, open_backlog.in_set(PhaseSet::Setting))
+            .add_systems(Update, scroll_backlog.in_set(PhaseSet::Setting))
+            .add_systems(Update, close_backlog.in_set(PhaseSet::Setting))
             .add_systems(Update, setup_window_sink.in_set(PhaseSet::Setting))
             .add_systems(Update, skip_typing_or_next.in_set(PhaseSet::Setting))
             .add_systems(Update, waiting_icon_setting.in_set(PhaseSet::Setting))
@@ -77,18 +197,44 @@ impl Plugin for MessageWindowPlugin {
             .add_systems(Update, restart_typing.in_set(PhaseSet::Setting))
             .add_systems(Update, settle_wating_icon.in_set(PhaseSet::Progress))
             .add_systems(Update, settle_lines.in_set(PhaseSet::Progress))
+            .add_systems(Update, build_nine_slices.in_set(PhaseSet::Progress))
+            .add_systems(
+                Update,
+                follow_speakers
+                    .in_set(PhaseSet::Progress)
+                    .before(popping_up)
+                    .before(sinking_down),
+            )
+            .add_systems(Update, fit_inline_images.in_set(PhaseSet::Progress))
+            .add_systems(Update, update_button_prompts.in_set(PhaseSet::Progress))
+            .add_systems(Update, draw_backlog.in_set(PhaseSet::Progress))
             .add_systems(Update, text_wipe.in_set(PhaseSet::Progress))
-            .add_systems(Update, scaling_up.in_set(PhaseSet::Progress))
-            .add_systems(Update, scaling_down.in_set(PhaseSet::Progress))
+            .add_systems(Update, text_fade.in_set(PhaseSet::Progress))
+            .add_systems(Update, text_drop.in_set(PhaseSet::Progress))
+            .add_systems(Update, text_pop.in_set(PhaseSet::Progress))
+            .add_systems(Update, text_slide.in_set(PhaseSet::Progress))
+            .add_systems(Update, text_scramble.in_set(PhaseSet::Progress))
+            .add_systems(Update, popping_up.in_set(PhaseSet::Progress))
+            .add_systems(Update, sinking_down.in_set(PhaseSet::Progress))
             .add_systems(Update, scroll_lines.in_set(PhaseSet::Progress))
+            .add_systems(Update, fade_lines.in_set(PhaseSet::Progress))
+            .add_systems(Update, slide_out_lines.in_set(PhaseSet::Progress))
             .add_systems(Update, simple_wait.in_set(PhaseSet::Progress))
             .add_systems(Update, open_window.in_set(PhaseSet::Change))
             .add_systems(Update, window_popper.in_set(PhaseSet::Change))
-            .add_systems(Update, start_window_sink.in_set(PhaseSet::Change))
+            .add_systems(Update, close_window.in_set(PhaseSet::Change))
+            .add_systems(
+                Update,
+                start_window_sink
+                    .in_set(PhaseSet::Change)
+                    .after(close_window),
+            )
             .add_systems(Update, add_new_text.in_set(PhaseSet::Change))
             .add_systems(Update, trigger_feeding_by_time.in_set(PhaseSet::Change))
             .add_systems(Update, trigger_feeding_by_event.in_set(PhaseSet::Change))
             .add_systems(Update, go_selected.in_set(PhaseSet::Change))
+            .add_systems(Update, auto_advance.in_set(PhaseSet::Change))
+            .add_systems(Update, skip_forward.in_set(PhaseSet::Change))
             .add_systems(
                 Update,
                 skip_feeding.in_set(PhaseSet::Change).after(add_new_text),
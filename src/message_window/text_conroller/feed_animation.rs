@@ -5,11 +5,12 @@ use super::super::*;
 pub struct FeedWaitingEvent {
     pub target_window: Entity,
     pub wait_sec: f32,
-    pub last_pos: Vec2,
 }
 
 #[derive(Event, Debug)]
-pub struct StartFeedingEvent;
+pub struct StartFeedingEvent {
+    pub target_window: Entity,
+}
 
 #[derive(Component, Debug)]
 pub struct WaitFeedingTrigger {
@@ -22,6 +23,26 @@ pub struct ScrollFeed {
     pub count: usize,
 }
 
+#[derive(Component, Debug)]
+pub struct FadeFeed {
+    pub fade_per_sec: f32,
+    pub remaining: f32,
+}
+
+#[derive(Component, Debug)]
+pub struct SlideFeed {
+    pub slide_per_sec: f32,
+    pub distance: Vec2,
+    pub remaining: f32,
+}
+
+type FadeTargets<'w, 's> = Query<
+    'w,
+    's,
+    (Option<&'static mut Text>, Option<&'static mut Sprite>),
+    Or<(With<MessageTextChar>, With<LineText>)>,
+>;
+
 #[allow(clippy::type_complexity)]
 pub fn setup_feed_starter(
     mut commands: Commands,
@@ -29,9 +50,13 @@ pub fn setup_feed_starter(
     text_box_query: Query<(Entity, &Parent, &GlobalTransform, &Sprite), With<TextBox>>,
     selected_query: Query<Entity, With<Selected>>,
     mut waitting_event: EventReader<FeedWaitingEvent>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
     type_registry: Res<AppTypeRegistry>,
 ) {
     for event in waitting_event.iter() {
+        lifecycle_event.send(WindowLifecycleEvent::PageShown {
+            window: event.target_window,
+        });
         for (w_entity, wbs) in &window_query {
             for (tb_entity, parent, tb_tf, tb_sp) in &text_box_query {
                 if event.target_window == w_entity && w_entity == parent.get() {
@@ -69,6 +94,8 @@ pub fn setup_feed_starter(
                                 commands.entity(s_entity).remove::<Selected>();
                             }
                             commands.entity(tb_entity).insert(Selected);
+                            lifecycle_event
+                                .send(WindowLifecycleEvent::WaitingInput { window: w_entity });
                         }
                     }
                 }
@@ -81,7 +108,8 @@ pub fn setup_feed_starter(
 pub fn trigger_feeding_by_event(
     mut commands: Commands,
     mut line_query: Query<(Entity, &Parent), With<MessageTextLine>>,
-    text_box_query: Query<&FeedingStyle>,
+    text_box_query: Query<(&FeedingStyle, &Parent)>,
+    window_query: Query<&WaitBrakerStyle>,
     mut icon_query: Query<(Entity, &mut Visibility), (With<WaitingIcon>, Without<MessageTextChar>)>,
     mut start_feeding_event: EventWriter<StartFeedingEvent>,
     mut events: EventReader<BMSEvent>,
@@ -91,17 +119,26 @@ pub fn trigger_feeding_by_event(
             target_text_box: Some(tb_entity),
         }) = event_wrapper.get_opt::<InputForFeeding>()
         {
+            let Ok((fs, tb_parent)) = text_box_query.get(tb_entity) else {
+                continue;
+            };
             for (l_entity, l_parent) in &mut line_query {
                 if l_parent.get() == tb_entity {
-                    if let Ok(fs) = text_box_query.get(tb_entity) {
-                        commands.entity(l_entity).insert(*fs);
-                    }
+                    commands.entity(l_entity).insert(*fs);
                 }
-                start_feeding_event.send(StartFeedingEvent);
             }
-            for (ic_entity, mut ic_vis) in &mut icon_query {
-                *ic_vis = Visibility::Hidden;
-                commands.entity(ic_entity).remove::<TypingTimer>();
+            start_feeding_event.send(StartFeedingEvent {
+                target_window: tb_parent.get(),
+            });
+            if let Ok(WaitBrakerStyle::Input {
+                icon_entity: Some(ic_entity),
+                ..
+            }) = window_query.get(tb_parent.get())
+            {
+                if let Ok((ic_entity, mut ic_vis)) = icon_query.get_mut(*ic_entity) {
+                    *ic_vis = Visibility::Hidden;
+                    commands.entity(ic_entity).remove::<TypingTimer>();
+                }
             }
         }
     }
@@ -109,12 +146,15 @@ pub fn trigger_feeding_by_event(
 
 pub fn trigger_feeding_by_time(
     mut commands: Commands,
-    mut text_box_query: Query<(Entity, &FeedingStyle, &mut WaitFeedingTrigger)>,
+    mut text_box_query: Query<
+        (Entity, &FeedingStyle, &mut WaitFeedingTrigger, &Parent),
+        Without<Paused>,
+    >,
     mut line_query: Query<(Entity, &Parent), With<MessageTextLine>>,
     mut start_feeding_event: EventWriter<StartFeedingEvent>,
     time: Res<Time>,
 ) {
-    for (tb_entity, fs, mut wft) in &mut text_box_query {
+    for (tb_entity, fs, mut wft, tb_parent) in &mut text_box_query {
         if wft.timer.tick(time.delta()).finished() {
             for (l_entity, parent) in &mut line_query {
                 if parent.get() == tb_entity {
@@ -122,35 +162,51 @@ pub fn trigger_feeding_by_time(
                 }
             }
             commands.entity(tb_entity).remove::<WaitFeedingTrigger>();
-            start_feeding_event.send(StartFeedingEvent);
+            start_feeding_event.send(StartFeedingEvent {
+                target_window: tb_parent.get(),
+            });
         }
     }
 }
 
 pub fn start_feeding(
     mut commands: Commands,
-    mut window_query: Query<(Entity, &mut WindowState, &WaitBrakerStyle)>,
+    mut window_query: Query<(Entity, &mut WindowState, &WaitBrakerStyle, &Speaker)>,
     text_box_query: Query<(Entity, &GlobalTransform, &Sprite, &Parent), With<TextBox>>,
     parent_query: Query<&Parent>,
-    line_query: Query<(Entity, &FeedingStyle), With<MessageTextLine>>,
-    start_feeding_event: EventReader<StartFeedingEvent>,
+    line_query: Query<(Entity, &FeedingStyle, &Parent), With<MessageTextLine>>,
+    line_text_query: Query<(&LineGlyphs, &Text, &Parent), With<LineText>>,
+    transform_query: Query<&Transform>,
+    mut start_feeding_event: EventReader<StartFeedingEvent>,
+    mut backlog: ResMut<Backlog>,
     type_registry: Res<AppTypeRegistry>,
 ) {
-    if start_feeding_event.is_empty() {
-        return;
-    };
-    for (w_entity, mut ws, wbs) in &mut window_query {
+    let targets = start_feeding_event
+        .iter()
+        .map(|e| e.target_window)
+        .collect::<Vec<Entity>>();
+    for (w_entity, mut ws, wbs, speaker) in &mut window_query {
+        if !targets.contains(&w_entity) {
+            continue;
+        }
         let target_lines = line_query
             .iter()
             .filter(|q| parent_query.iter_ancestors(q.0).any(|e| e == w_entity))
-            .collect::<Vec<(Entity, &FeedingStyle)>>();
-        for (l_entity, fs) in target_lines.iter() {
+            .collect::<Vec<(Entity, &FeedingStyle, &Parent)>>();
+        record_fed_lines(
+            &target_lines,
+            &line_text_query,
+            &transform_query,
+            &mut backlog,
+            &speaker.name,
+        );
+        for (l_entity, fs, l_parent) in target_lines.iter() {
             match fs {
                 FeedingStyle::Scroll {
                     size: fs_size,
                     sec: fs_sec,
                 } => {
-                    let line_size = line_query.iter().count();
+                    let line_size = target_lines.len();
                     let line_count = if *fs_size == 0 || line_size < *fs_size {
                         0
                     } else {
@@ -159,10 +215,45 @@ pub fn start_feeding(
                     commands.entity(*l_entity).insert(ScrollFeed {
                         line_per_sec: target_lines.len() as f32 / *fs_sec,
                         count: line_count,
-                    })
+                    });
+                }
+                FeedingStyle::Fade { sec: fs_sec } => {
+                    commands.entity(*l_entity).insert(FadeFeed {
+                        fade_per_sec: 1.0 / *fs_sec,
+                        remaining: 1.0,
+                    });
+                }
+                FeedingStyle::SlideOut {
+                    sec: fs_sec,
+                    to: direction,
+                } => {
+                    let box_size = text_box_query
+                        .get(l_parent.get())
+                        .ok()
+                        .and_then(|tb| tb.2.custom_size)
+                        .unwrap_or_default();
+                    let distance = match direction {
+                        FeedDirection::Left => Vec2::new(-box_size.x, 0.),
+                        FeedDirection::Up => Vec2::new(0., box_size.y),
+                    };
+                    commands.entity(*l_entity).insert(SlideFeed {
+                        slide_per_sec: 1.0 / *fs_sec,
+                        distance,
+                        remaining: 1.0,
+                    });
+                }
+                FeedingStyle::Clear => {
+                    commands.entity(*l_entity).despawn_recursive();
                 }
             };
         }
+        if target_lines
+            .iter()
+            .all(|(_, fs, _)| **fs == FeedingStyle::Clear)
+        {
+            *ws = WindowState::Typing;
+            continue;
+        }
         *ws = WindowState::Feeding;
         for (tb_entity, tb_tf, tb_sp, tb_parent) in &text_box_query {
             if tb_parent.get() == w_entity {
@@ -175,15 +266,82 @@ pub fn start_feeding(
     }
 }
 
+fn record_fed_lines(
+    target_lines: &[(Entity, &FeedingStyle, &Parent)],
+    line_text_query: &Query<(&LineGlyphs, &Text, &Parent), With<LineText>>,
+    transform_query: &Query<&Transform>,
+    backlog: &mut Backlog,
+    speaker: &str,
+) {
+    let mut sorted = target_lines
+        .iter()
+        .map(|(l_entity, fs, _)| {
+            let y = transform_query
+                .get(*l_entity)
+                .map(|tf| tf.translation.y)
+                .unwrap_or_default();
+            (*l_entity, **fs, y)
+        })
+        .collect::<Vec<(Entity, FeedingStyle, f32)>>();
+    sorted.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+    let fed_size = match sorted.first() {
+        Some((_, FeedingStyle::Scroll { size: s, .. }, _)) if *s != 0 => *s,
+        _ => sorted.len(),
+    };
+    let lines = sorted
+        .iter()
+        .take(fed_size)
+        .map(|(l_entity, ..)| line_spans(*l_entity, line_text_query))
+        .collect();
+    backlog.push_page(speaker, lines);
+}
+
+// 最後のページは送られずに窓ごと消えるので、沈む前や閉じる前に残りの行を記録する。
+pub fn record_remaining_lines(
+    window: Entity,
+    line_query: &Query<(Entity, &Transform), With<MessageTextLine>>,
+    line_text_query: &Query<(&LineGlyphs, &Text, &Parent), With<LineText>>,
+    parent_query: &Query<&Parent>,
+    backlog: &mut Backlog,
+    speaker: &str,
+) {
+    let mut sorted = line_query
+        .iter()
+        .filter(|(l_entity, _)| parent_query.iter_ancestors(*l_entity).any(|e| e == window))
+        .map(|(l_entity, tf)| (l_entity, tf.translation.y))
+        .collect::<Vec<(Entity, f32)>>();
+    sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    let lines = sorted
+        .iter()
+        .map(|(l_entity, _)| line_spans(*l_entity, line_text_query))
+        .collect();
+    backlog.push_page(speaker, lines);
+}
+
+fn line_spans(
+    line: Entity,
+    line_text_query: &Query<(&LineGlyphs, &Text, &Parent), With<LineText>>,
+) -> Vec<BacklogSpan> {
+    line_text_query
+        .iter()
+        .find(|(.., p)| p.get() == line)
+        .map(|(lg, text, _)| lg.spans(text))
+        .unwrap_or_default()
+}
+
 pub fn scroll_lines(
     mut commands: Commands,
-    mut window_query: Query<(Entity, &mut WindowState)>,
+    mut window_query: Query<(Entity, &mut WindowState), Without<Paused>>,
     mut line_query: Query<(Entity, &mut Transform, &Sprite, &mut ScrollFeed)>,
+    text_box_query: Query<(&FeedingStyle, &Parent), With<TextBox>>,
     parent_query: Query<&Parent>,
     time: Res<Time>,
 ) {
     for (w_entity, mut ws) in &mut window_query {
-        if *ws == WindowState::Feeding {
+        let is_scroll = text_box_query
+            .iter()
+            .any(|(fs, p)| p.get() == w_entity && matches!(fs, FeedingStyle::Scroll { .. }));
+        if *ws == WindowState::Feeding && is_scroll {
             let mut target_lines = line_query
                 .iter_mut()
                 .filter(|q| parent_query.iter_ancestors(q.0).any(|e| e == w_entity))
@@ -213,3 +371,224 @@ pub fn scroll_lines(
         }
     }
 }
+
+pub fn fade_lines(
+    mut commands: Commands,
+    mut window_query: Query<(Entity, &mut WindowState), Without<Paused>>,
+    mut line_query: Query<(Entity, &mut FadeFeed, &Children)>,
+    mut char_query: FadeTargets,
+    parent_query: Query<&Parent>,
+    time: Res<Time>,
+) {
+    for (w_entity, mut ws) in &mut window_query {
+        if *ws != WindowState::Feeding {
+            continue;
+        }
+        let mut is_finished = false;
+        for (l_entity, mut ff, children) in &mut line_query {
+            if !parent_query.iter_ancestors(l_entity).any(|e| e == w_entity) {
+                continue;
+            }
+            let next = ff.remaining - time.delta_seconds() * ff.fade_per_sec;
+            if next <= 0. {
+                commands.entity(l_entity).despawn_recursive();
+                is_finished = true;
+            } else {
+                fade_texts(children, &mut char_query, next / ff.remaining);
+                ff.remaining = next;
+            }
+        }
+        if is_finished {
+            *ws = WindowState::Typing;
+        }
+    }
+}
+
+pub fn slide_out_lines(
+    mut commands: Commands,
+    mut window_query: Query<(Entity, &mut WindowState), Without<Paused>>,
+    mut line_query: Query<(Entity, &mut Transform, &mut SlideFeed, &Children)>,
+    mut char_query: FadeTargets,
+    parent_query: Query<&Parent>,
+    time: Res<Time>,
+) {
+    for (w_entity, mut ws) in &mut window_query {
+        if *ws != WindowState::Feeding {
+            continue;
+        }
+        let mut is_finished = false;
+        for (l_entity, mut tf, mut sf, children) in &mut line_query {
+            if !parent_query.iter_ancestors(l_entity).any(|e| e == w_entity) {
+                continue;
+            }
+            let step = time.delta_seconds() * sf.slide_per_sec;
+            let next = sf.remaining - step;
+            if next <= 0. {
+                commands.entity(l_entity).despawn_recursive();
+                is_finished = true;
+            } else {
+                tf.translation += (sf.distance * step).extend(0.);
+                fade_texts(children, &mut char_query, next / sf.remaining);
+                sf.remaining = next;
+            }
+        }
+        if is_finished {
+            *ws = WindowState::Typing;
+        }
+    }
+}
+
+fn fade_texts(children: &Children, char_query: &mut FadeTargets, ratio: f32) {
+    let mut texts = char_query.iter_many_mut(children);
+    while let Some((text_opt, sprite_opt)) = texts.fetch_next() {
+        if let Some(mut text) = text_opt {
+            for section in text.sections.iter_mut() {
+                let alpha = section.style.color.a();
+                section.style.color.set_a(alpha * ratio);
+            }
+        }
+        if let Some(mut sprite) = sprite_opt {
+            let alpha = sprite.color.a();
+            sprite.color.set_a(alpha * ratio);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::window_controller::sinkdown::*;
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_event::<GoSinking>()
+            .add_event::<CloseWindowEvent>()
+            .add_event::<WindowLifecycleEvent>()
+            .init_resource::<Backlog>()
+            .add_systems(Update, (close_window, start_window_sink).chain());
+        app
+    }
+
+    fn spawn_window(app: &mut App, sink_type: SinkDownType) -> Entity {
+        let window = app
+            .world
+            .spawn((
+                MessageWindow {
+                    name: "test".to_string(),
+                },
+                Speaker {
+                    name: "キツネ".to_string(),
+                },
+                WindowState::Waiting,
+                Transform::default(),
+                sink_type,
+            ))
+            .id();
+        for (i, word) in ["こんにちは", "はじめまして"].iter().enumerate() {
+            let line = app
+                .world
+                .spawn((
+                    MessageTextLine {
+                        alignment: TextAlignment::Left,
+                    },
+                    Transform::from_xyz(0., -27. * i as f32, 0.),
+                ))
+                .set_parent(window)
+                .id();
+            let glyphs = word
+                .chars()
+                .map(|_| LineGlyph {
+                    x: 0.,
+                    advance: 27.,
+                    font_size: 27.,
+                    alpha: 1.,
+                    reveal_at: 0.,
+                    writing: WritingStyle::Put,
+                    reveal: GlyphReveal::Typed,
+                    is_separated: false,
+                    blip: None,
+                })
+                .collect();
+            let sections = word
+                .chars()
+                .map(|c| TextSection::new(c.to_string(), TextStyle::default()));
+            app.world
+                .spawn((
+                    LineText,
+                    LineGlyphs {
+                        glyphs,
+                        ..default()
+                    },
+                    Text::from_sections(sections),
+                ))
+                .set_parent(line);
+        }
+        window
+    }
+
+    fn recorded_lines(app: &App) -> Vec<(String, Vec<String>)> {
+        app.world
+            .resource::<Backlog>()
+            .entries
+            .iter()
+            .map(|e| {
+                let lines = e
+                    .lines
+                    .iter()
+                    .map(|l| l.iter().map(|s| s.text.as_str()).collect())
+                    .collect();
+                (e.speaker.clone(), lines)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_lines_on_sinking() {
+        let mut app = test_app();
+        let window = spawn_window(&mut app, SinkDownType::Fix);
+        app.world
+            .resource_mut::<Events<GoSinking>>()
+            .send(GoSinking {
+                target: Some(window),
+                sink_type: SinkDownType::Fade {
+                    sec: 0.5,
+                    easing: Easing::Linear,
+                },
+            });
+        app.update();
+        let expected = vec![(
+            "キツネ".to_string(),
+            vec!["こんにちは".to_string(), "はじめまして".to_string()],
+        )];
+        assert_eq!(recorded_lines(&app), expected);
+        app.world
+            .resource_mut::<Events<GoSinking>>()
+            .send(GoSinking {
+                target: Some(window),
+                sink_type: SinkDownType::Fade {
+                    sec: 0.5,
+                    easing: Easing::Linear,
+                },
+            });
+        app.update();
+        assert_eq!(recorded_lines(&app), expected);
+    }
+
+    #[test]
+    fn test_record_lines_on_close() {
+        let mut app = test_app();
+        let window = spawn_window(&mut app, SinkDownType::Fix);
+        app.world
+            .resource_mut::<Events<CloseWindowEvent>>()
+            .send(CloseWindowEvent { window });
+        app.update();
+        assert_eq!(
+            recorded_lines(&app),
+            vec![(
+                "キツネ".to_string(),
+                vec!["こんにちは".to_string(), "はじめまして".to_string()],
+            )]
+        );
+        assert!(app.world.get_entity(window).is_none());
+    }
+}
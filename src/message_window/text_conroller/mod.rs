@@ -3,15 +3,22 @@ use bevy::{
     prelude::*,
     render::view::{RenderLayers, Visibility},
     sprite::Anchor,
-    text::TextAlignment,
+    text::{TextAlignment, TextLayoutInfo},
+    window::PrimaryWindow,
 };
+use std::collections::HashMap;
 
 pub mod feed_animation;
+pub mod text_decorations;
+pub mod text_effects;
 pub mod typing_animations;
+pub mod typing_sound;
 
 use super::*;
-use crate::utility::*;
 use feed_animation::*;
+use text_decorations::*;
+use text_effects::*;
+use typing_sound::*;
 
 #[derive(Component, Debug)]
 pub struct MessageTextLine {
@@ -21,6 +28,99 @@ pub struct MessageTextLine {
 #[derive(Component, Debug)]
 pub struct MessageTextChar;
 
+// 行内の画像はLineTextに全角スペースを置いて場所を取り、絵そのものはMessageTextCharのスプライトで出す。
+#[derive(Component, Debug)]
+pub struct InlineImage {
+    pub cell: Vec2,
+}
+
+#[derive(Component, Debug)]
+pub struct ButtonPrompt {
+    pub action: String,
+}
+
+const IMAGE_PLACEHOLDER: &str = "\u{2003}";
+
+// 1行を1つのText2dにまとめて、表示済みの文字数(cursor)で出していく。
+// 文字ごとに動かす必要のある書き方やエフェクトの文字だけMessageTextCharとして別に作り、
+// LineTextの側には透明な文字を置いて位置を合わせる。
+#[derive(Component, Debug)]
+pub struct LineText;
+
+// gapsは両端揃えで各文字の前に足す隙間。行を作るときに折り返し位置まで流してみて決める。
+#[derive(Component, Debug, Default)]
+pub struct LineGlyphs {
+    pub glyphs: Vec<LineGlyph>,
+    pub cursor: usize,
+    pub elapsed: f32,
+    pub next_at: f32,
+    pub gaps: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub struct LineGlyph {
+    pub x: f32,
+    pub advance: f32,
+    pub font_size: f32,
+    pub alpha: f32,
+    pub reveal_at: f32,
+    pub writing: WritingStyle,
+    pub reveal: GlyphReveal,
+    pub is_separated: bool,
+    pub blip: Option<BlipSound>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlyphReveal {
+    Waiting,
+    Fading { fade_per_sec: f32, progress: f32 },
+    Typed,
+}
+
+impl LineGlyphs {
+    pub fn pen(&self) -> f32 {
+        self.glyphs
+            .last()
+            .map(|g| g.x + g.advance)
+            .unwrap_or_default()
+    }
+
+    pub fn remaining_secs(&self) -> f32 {
+        (self.next_at - self.elapsed).max(0.)
+    }
+
+    pub fn spans(&self, text: &Text) -> Vec<BacklogSpan> {
+        let mut spans: Vec<BacklogSpan> = vec![];
+        for (glyph, section) in self.glyphs.iter().zip(text.sections.iter()) {
+            let style = TextStyle {
+                color: section.style.color.with_a(glyph.alpha),
+                ..section.style.clone()
+            };
+            match spans.last_mut() {
+                Some(last)
+                    if last.style.font == style.font
+                        && last.style.font_size == style.font_size
+                        && last.style.color == style.color =>
+                {
+                    last.text.push_str(&section.value)
+                }
+                _ => spans.push(BacklogSpan {
+                    text: section.value.clone(),
+                    style,
+                }),
+            }
+        }
+        spans
+    }
+
+    pub fn line_height(&self) -> Option<f32> {
+        self.glyphs
+            .iter()
+            .map(|g| g.font_size)
+            .reduce(|x, y| if x > y { x } else { y })
+    }
+}
+
 #[derive(Bundle, Debug)]
 struct CharBundle {
     text_char: MessageTextChar,
@@ -28,6 +128,17 @@ struct CharBundle {
     text2d: Text2dBundle,
     layer: RenderLayers,
     writing: WritingStyle,
+    effects: TextEffects,
+}
+
+#[derive(Bundle)]
+struct ImageBundle {
+    text_char: MessageTextChar,
+    inline_image: InlineImage,
+    timer: TypingTimer,
+    sprite: SpriteBundle,
+    layer: RenderLayers,
+    writing: WritingStyle,
 }
 
 #[derive(Bundle)]
@@ -36,6 +147,60 @@ struct LineBundle {
     sprites: SpriteBundle,
 }
 
+#[derive(Bundle)]
+struct LineTextBundle {
+    line_text: LineText,
+    glyphs: LineGlyphs,
+    text2d: Text2dBundle,
+    layer: RenderLayers,
+}
+
+// ページに収まらないときの扱いと縦の寄せ方。
+// ページの頭で一度だけ測って、文字数の上限と縮小率、1行目を下げる量を持っておく。
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PageFit {
+    pub font_scale: f32,
+    pub remaining: Option<usize>,
+    pub with_ellipsis: bool,
+    pub offset_y: f32,
+}
+
+impl Default for PageFit {
+    fn default() -> Self {
+        PageFit {
+            font_scale: 1.,
+            remaining: None,
+            with_ellipsis: false,
+            offset_y: 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PageLayout {
+    max_width: f32,
+    max_height: f32,
+    font_scale: f32,
+    offset_y: f32,
+}
+
+impl PageLayout {
+    fn font_size(&self, config: &TypeTextConfig) -> f32 {
+        config.text_style.font_size * self.font_scale
+    }
+
+    // 縦寄せで1行目が下がっていても、ページの1行目かどうかは下げた位置から数える。
+    fn is_first_line(&self, config: &TypeTextConfig, last_y: f32) -> bool {
+        last_y >= -self.offset_y - self.font_size(config)
+    }
+}
+
+struct NewGlyph {
+    section: TextSection,
+    glyph: LineGlyph,
+    separated: Option<CharBundle>,
+}
+
 #[derive(Component, Clone, Debug)]
 pub struct TypingTimer {
     pub timer: Timer,
@@ -44,18 +209,7 @@ pub struct TypingTimer {
 #[derive(SystemParam, Debug)]
 #[allow(clippy::type_complexity)]
 pub struct LastTextData<'w, 's> {
-    text: Query<
-        'w,
-        's,
-        (
-            Entity,
-            &'static Transform,
-            &'static Text,
-            &'static TypingTimer,
-            &'static Parent,
-        ),
-        (With<Current>, With<MessageTextChar>),
-    >,
+    line_text: Query<'w, 's, (Entity, &'static LineGlyphs, &'static Parent), With<LineText>>,
     line: Query<
         'w,
         's,
@@ -71,6 +225,10 @@ type TextBoxData<'w, 's> = Query<
         Entity,
         &'static Sprite,
         &'static TypeTextConfig,
+        &'static OverflowPolicy,
+        &'static VerticalAlignment,
+        Option<&'static PageFit>,
+        &'static mut ScriptCursor,
         &'static Parent,
     ),
     (With<Current>, With<TextBox>),
@@ -78,75 +236,230 @@ type TextBoxData<'w, 's> = Query<
 
 pub fn add_new_text(
     mut commands: Commands,
-    mut window_query: Query<(Entity, &mut LoadedScript, &mut WindowState)>,
-    text_box_query: TextBoxData,
+    mut window_query: Query<(Entity, &mut LoadedScript, &mut WindowState), Without<Paused>>,
+    mut text_box_query: TextBoxData,
     last_data: LastTextData,
     app_type_registry: Res<AppTypeRegistry>,
     mut wrapper: EventWriter<BMSEvent>,
     mut ps_event: EventWriter<FeedWaitingEvent>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
     fonts: Res<Assets<Font>>,
-    mut pending: Local<Option<Order>>,
-    mut in_cr: Local<bool>,
+    mut coverage: ResMut<GlyphCoverage>,
+    asset_server: Res<AssetServer>,
+    button_icons: Res<ButtonIconSets>,
+    last_device: Res<LastInputDevice>,
 ) {
     for (w_ent, mut script, mut ws) in &mut window_query {
-        for (tb_ent, tb_spr, config, parent) in &text_box_query {
+        for (tb_ent, tb_spr, config, overflow, vertical, fit_opt, mut cursor, parent) in
+            &mut text_box_query
+        {
             if *ws != WindowState::Typing || w_ent != parent.get() {
                 continue;
             }
-            let (mut last_line_opt, mut last_text_opt, mut last_x, mut last_y, mut last_timer) =
+            let ScriptCursor {
+                pending,
+                in_cr,
+                is_finished,
+                page_orders,
+                page_style,
+            } = &mut *cursor;
+            if page_style.is_none() {
+                *page_style = Some((config.snapshot(), config.style_stack.clone()));
+            }
+            let (mut last_line_opt, mut line_text_opt, mut last_x, mut last_y, mut last_timer) =
                 initialize_typing_data(&last_data, tb_ent);
             let Vec2 {
                 x: max_width,
                 y: max_height,
             } = tb_spr.custom_size.unwrap_or_default();
+            let mut fit = fit_opt.copied().unwrap_or_else(|| {
+                let orders = pending.iter().chain(
+                    script
+                        .order_list
+                        .as_deref()
+                        .unwrap_or_default()
+                        .iter()
+                        .rev(),
+                );
+                let layout = PageLayout {
+                    max_width,
+                    max_height,
+                    font_scale: 1.,
+                    offset_y: 0.,
+                };
+                let start = (last_x, last_y, *in_cr || last_line_opt.is_none());
+                fit_page(
+                    *overflow,
+                    *vertical,
+                    orders,
+                    config,
+                    fonts.as_ref(),
+                    &mut coverage,
+                    layout,
+                    start,
+                )
+            });
+            let layout = PageLayout {
+                max_width,
+                max_height,
+                font_scale: fit.font_scale,
+                offset_y: fit.offset_y,
+            };
+            if last_line_opt.is_none() {
+                last_y = -fit.offset_y;
+            }
+            let (mut line_gaps, mut line_index) = line_text_opt
+                .and_then(|e| last_data.line_text.get(e).ok())
+                .map(|(_, lg, _)| (lg.gaps.clone(), lg.glyphs.len()))
+                .unwrap_or_default();
+            let mut is_page_end = false;
             loop {
-                let next_order = get_next_order(&pending, &mut script.order_list, *in_cr);
+                let is_popping = pending.is_none() && !*in_cr;
+                let next_order = get_next_order(pending, &mut script.order_list, *in_cr);
+                if let Some(order) = next_order.as_ref().filter(|_| is_popping) {
+                    page_orders.push(order.clone());
+                }
                 match next_order {
-                    Some(Order::Type {
-                        character: new_word,
-                    }) => {
+                    Some(
+                        ref order @ (Order::Type { .. }
+                        | Order::Image { .. }
+                        | Order::ButtonPrompt { .. }),
+                    ) => {
+                        let (new_word, image_path) = match (fit.remaining, order) {
+                            (Some(0), _) if fit.with_ellipsis => ("…".to_string(), None),
+                            (Some(0), _) => {
+                                *pending = None;
+                                continue;
+                            }
+                            (_, Order::Image { path: p }) => {
+                                (IMAGE_PLACEHOLDER.to_string(), Some(p.clone()))
+                            }
+                            (_, Order::ButtonPrompt { action: a }) => {
+                                let Some(p) = button_icons.path(a, *last_device) else {
+                                    warn!("button icon for \"{a}\" is not registered");
+                                    *pending = None;
+                                    continue;
+                                };
+                                (IMAGE_PLACEHOLDER.to_string(), Some(p.to_string()))
+                            }
+                            (_, Order::Type { character: c }) => (c.clone(), None),
+                            _ => unreachable!(),
+                        };
+                        let prompt = match order {
+                            Order::ButtonPrompt { action: a } => {
+                                Some(ButtonPrompt { action: a.clone() })
+                            }
+                            _ => None,
+                        };
+                        let gap = line_gaps.get(line_index).copied().unwrap_or_default();
                         let new_text_opt = make_new_text(
-                            new_word,
+                            &new_word,
+                            gap,
                             config,
                             &mut last_x,
                             last_y,
                             &mut last_timer,
                             fonts.as_ref(),
-                            max_width,
+                            &mut coverage,
+                            layout,
                         );
-                        let (Some(new_text), Some(last_line)) = (new_text_opt, last_line_opt) else {
+                        let (Some(new_text), Some(last_line), Some(line_text)) =
+                            (new_text_opt, last_line_opt, line_text_opt)
+                        else {
+                            if fit.remaining == Some(0) {
+                                fit.with_ellipsis = false;
+                                *pending = None;
+                                continue;
+                            }
                             *pending = next_order;
                             *in_cr = true;
                             continue;
                         };
-                        let new_text_entity = commands.spawn((new_text, Current)).id();
-                        if let Some(last_text) = last_text_opt {
-                            commands.entity(last_text).remove::<Current>();
+                        let NewGlyph {
+                            section,
+                            mut glyph,
+                            separated,
+                        } = new_text;
+                        line_index += 1;
+                        if let Some(path) = image_path {
+                            glyph.is_separated = true;
+                            let image_bundle =
+                                make_inline_image(&glyph, config, &asset_server, path);
+                            let image_entity = commands.spawn(image_bundle).id();
+                            if let Some(prompt) = prompt {
+                                commands.entity(image_entity).insert(prompt);
+                            }
+                            commands.entity(last_line).add_child(image_entity);
+                        } else {
+                            if let Some(char_bundle) = separated {
+                                let char_entity = commands.spawn(char_bundle).id();
+                                spawn_text_decorations(&mut commands, char_entity, config);
+                                commands.entity(last_line).add_child(char_entity);
+                            }
+                            glyph.blip = make_blip(&new_word, config);
+                        }
+                        let next_sec = last_timer.timer.remaining_secs();
+                        commands.add(move |w: &mut World| {
+                            append_glyph(w, line_text, section, glyph, next_sec);
+                        });
+                        match fit.remaining.as_mut() {
+                            Some(0) => fit.with_ellipsis = false,
+                            Some(r) => *r -= 1,
+                            None => (),
                         }
-                        last_text_opt = Some(new_text_entity);
-                        commands.entity(last_line).add_child(new_text_entity);
                         *pending = None;
                         *in_cr = false;
                     }
                     Some(Order::CarriageReturn) => {
+                        if fit.remaining == Some(0) {
+                            *in_cr = false;
+                            continue;
+                        }
+                        let gaps = if config.justify {
+                            let orders = pending.iter().chain(
+                                script
+                                    .order_list
+                                    .as_deref()
+                                    .unwrap_or_default()
+                                    .iter()
+                                    .rev(),
+                            );
+                            justify_gaps(orders, config, fonts.as_ref(), &mut coverage, layout)
+                        } else {
+                            vec![]
+                        };
                         let new_line_opt =
-                            make_empty_line(config, &mut last_x, &mut last_y, max_height);
-                        let Some(new_line) = new_line_opt else {
-                            send_feed_event(&mut ps_event, w_ent, &last_timer, &mut ws, last_x, last_y);
+                            make_empty_line(config, &mut last_x, &mut last_y, layout, gaps.clone());
+                        let Some((new_line, new_line_text)) = new_line_opt else {
+                            if *overflow != OverflowPolicy::Paginate {
+                                fit.remaining = Some(0);
+                                fit.with_ellipsis = false;
+                                *in_cr = false;
+                                continue;
+                            }
+                            send_feed_event(&mut ps_event, w_ent, &last_timer, &mut ws);
+                            is_page_end = true;
                             *in_cr = true;
                             break;
                         };
                         let new_line_entity = commands.spawn((new_line, Current)).id();
+                        let line_text_entity = commands.spawn(new_line_text).id();
+                        spawn_text_decorations(&mut commands, line_text_entity, config);
+                        commands.entity(new_line_entity).add_child(line_text_entity);
                         if let Some(last_line) = last_line_opt {
                             commands.entity(last_line).remove::<Current>();
                         }
                         last_line_opt = Some(new_line_entity);
+                        line_text_opt = Some(line_text_entity);
+                        line_gaps = gaps;
+                        line_index = 0;
                         commands.entity(tb_ent).add_child(new_line_entity);
                         *in_cr = false;
                         continue;
                     }
                     Some(Order::PageFeed) => {
-                        send_feed_event(&mut ps_event, w_ent, &last_timer, &mut ws, last_x, last_y);
+                        send_feed_event(&mut ps_event, w_ent, &last_timer, &mut ws);
+                        is_page_end = true;
                         *in_cr = true;
                         break;
                     }
@@ -156,6 +469,7 @@ pub fn add_new_text(
                         if let Ok(reflect_value) = event_opt {
                             wrapper.send(BMSEvent {
                                 value: reflect_value,
+                                window: Some(w_ent),
                             })
                         }
                         break;
@@ -163,36 +477,313 @@ pub fn add_new_text(
                     None => break,
                 }
             }
+            let is_run_out = pending.is_none()
+                && script
+                    .order_list
+                    .as_ref()
+                    .is_some_and(|list| list.is_empty());
+            if is_run_out && !*is_finished {
+                lifecycle_event.send(WindowLifecycleEvent::ScriptFinished { window: w_ent });
+                *is_finished = true;
+            }
+            if is_page_end {
+                *page_orders = pending.iter().cloned().collect();
+                *page_style = None;
+                commands.entity(tb_ent).remove::<PageFit>();
+            } else if (*overflow != OverflowPolicy::Paginate || *vertical != VerticalAlignment::Top)
+                && script.order_list.is_some()
+            {
+                commands.entity(tb_ent).insert(fit);
+            }
         }
     }
 }
 
+fn fit_page<'a>(
+    policy: OverflowPolicy,
+    vertical: VerticalAlignment,
+    orders: impl Iterator<Item = &'a Order> + Clone,
+    config: &TypeTextConfig,
+    font_assets: &Assets<Font>,
+    coverage: &mut GlyphCoverage,
+    layout: PageLayout,
+    start: (f32, f32, bool),
+) -> PageFit {
+    let mut fit = match policy {
+        OverflowPolicy::Paginate => PageFit::default(),
+        OverflowPolicy::Clip => PageFit {
+            remaining: count_fitting(
+                orders.clone(),
+                config,
+                font_assets,
+                coverage,
+                layout,
+                start,
+                false,
+            )
+            .0,
+            ..default()
+        },
+        OverflowPolicy::Ellipsis => {
+            let (remaining, _) = count_fitting(
+                orders.clone(),
+                config,
+                font_assets,
+                coverage,
+                layout,
+                start,
+                true,
+            );
+            PageFit {
+                remaining,
+                with_ellipsis: remaining.is_some(),
+                ..default()
+            }
+        }
+        OverflowPolicy::ShrinkToFit => {
+            let mut scaled = layout;
+            loop {
+                let (remaining, _) = count_fitting(
+                    orders.clone(),
+                    config,
+                    font_assets,
+                    coverage,
+                    scaled,
+                    start,
+                    false,
+                );
+                if remaining.is_none() || scaled.font_size(config) <= 1. {
+                    break PageFit {
+                        font_scale: scaled.font_scale,
+                        remaining,
+                        ..default()
+                    };
+                }
+                scaled.font_scale *= 0.9;
+            }
+        }
+    };
+    let ratio = match vertical {
+        VerticalAlignment::Top => return fit,
+        VerticalAlignment::Middle => 0.5,
+        VerticalAlignment::Bottom => 1.,
+    };
+    let scaled = PageLayout {
+        font_scale: fit.font_scale,
+        ..layout
+    };
+    let (_, height) = count_fitting(orders, config, font_assets, coverage, scaled, start, false);
+    fit.offset_y = (layout.max_height - height).max(0.) * ratio;
+    fit
+}
+
+static CR_ORDER: Order = Order::CarriageReturn;
+
+// make_new_textとmake_empty_lineと同じ寸法でページを流してみて、
+// 収まらなければ入る文字数を、収まればそのページの高さも返す。
+fn count_fitting<'a>(
+    orders: impl Iterator<Item = &'a Order>,
+    config: &TypeTextConfig,
+    font_assets: &Assets<Font>,
+    coverage: &mut GlyphCoverage,
+    layout: PageLayout,
+    (mut last_x, mut last_y, in_cr): (f32, f32, bool),
+    with_ellipsis: bool,
+) -> (Option<usize>, f32) {
+    let font_size = layout.font_size(config);
+    let ellipsis_advance = if with_ellipsis {
+        char_metrics("…", config, font_assets, coverage, layout).2
+    } else {
+        0.
+    };
+    let mut line_advances = vec![];
+    let mut count = 0usize;
+    for order in in_cr.then_some(&CR_ORDER).into_iter().chain(orders) {
+        let advance = match order {
+            Order::Type { character: c } => {
+                char_metrics(c, config, font_assets, coverage, layout).2
+            }
+            Order::Image { .. } | Order::ButtonPrompt { .. } => {
+                char_metrics(IMAGE_PLACEHOLDER, config, font_assets, coverage, layout).2
+            }
+            Order::CarriageReturn => 0.,
+            Order::PageFeed => break,
+            Order::ThroghEvent { .. } => continue,
+        };
+        let is_wrapped = last_x + advance > layout.max_width;
+        if *order == Order::CarriageReturn || is_wrapped {
+            if last_y - font_size < -layout.max_height {
+                for a in line_advances.iter().rev() {
+                    if last_x + ellipsis_advance <= layout.max_width {
+                        break;
+                    }
+                    last_x -= a;
+                    count -= 1;
+                }
+                return (Some(count), layout.max_height);
+            }
+            last_x = 0.;
+            last_y -= font_size;
+            line_advances.clear();
+        }
+        if let Order::Type { .. } | Order::Image { .. } | Order::ButtonPrompt { .. } = order {
+            last_x += advance;
+            line_advances.push(advance);
+            count += 1;
+        }
+    }
+    (None, -last_y)
+}
+
+// 折り返しで終わる行だけ、単語の間(空白が無ければ文字の間)に余りを配る。
+// 段落の最後の行は揃えない。
+fn justify_gaps<'a>(
+    orders: impl Iterator<Item = &'a Order>,
+    config: &TypeTextConfig,
+    font_assets: &Assets<Font>,
+    coverage: &mut GlyphCoverage,
+    layout: PageLayout,
+) -> Vec<f32> {
+    let mut glyphs: Vec<(f32, bool)> = vec![];
+    let mut width = 0.;
+    let mut is_wrapped = false;
+    for order in orders {
+        let (advance, is_space) = match order {
+            Order::Type { character: c } => (
+                char_metrics(c, config, font_assets, coverage, layout).2,
+                c.chars().all(char::is_whitespace),
+            ),
+            Order::Image { .. } | Order::ButtonPrompt { .. } => (
+                char_metrics(IMAGE_PLACEHOLDER, config, font_assets, coverage, layout).2,
+                false,
+            ),
+            Order::ThroghEvent { .. } => continue,
+            Order::CarriageReturn | Order::PageFeed => break,
+        };
+        if width + advance > layout.max_width {
+            is_wrapped = true;
+            break;
+        }
+        width += advance;
+        glyphs.push((advance, is_space));
+    }
+    let content = glyphs.iter().rposition(|g| !g.1).map_or(0, |i| i + 1);
+    let by_word = (1..content).any(|i| glyphs[i].1);
+    let is_gap = |i: usize| i > 0 && i < content && (!by_word || (!glyphs[i].1 && glyphs[i - 1].1));
+    let count = (0..glyphs.len()).filter(|i| is_gap(*i)).count();
+    if !is_wrapped || count == 0 {
+        return vec![];
+    }
+    // 誤差で行末の文字が押し出されないように、ほんの少しだけ余らせる。
+    let extra = (layout.max_width - width) * 0.999 / count as f32;
+    (0..glyphs.len())
+        .map(|i| if is_gap(i) { extra } else { 0. })
+        .collect()
+}
+
+// 1つのText2dにまとめた行は文字ごとに動かせないので、
+// 配置が計算し直されたときにgapsの分だけ横にずらす。縁取りと影も同じだけずらす。
+#[allow(clippy::type_complexity)]
+pub fn justify_line_layouts(
+    mut line_texts: Query<
+        (&LineGlyphs, &mut TextLayoutInfo, Option<&Children>),
+        (With<LineText>, Without<TextDecoration>),
+    >,
+    mut decorations: Query<&mut TextLayoutInfo, (With<TextDecoration>, Without<LineText>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let scale_factor = window_query
+        .get_single()
+        .map(|w| w.scale_factor() as f32)
+        .unwrap_or(1.);
+    for (lg, mut layout, children_opt) in &mut line_texts {
+        if lg.gaps.is_empty() {
+            continue;
+        }
+        let shifts = lg
+            .gaps
+            .iter()
+            .scan(0., |total, g| {
+                *total += g;
+                Some(*total * scale_factor)
+            })
+            .collect::<Vec<f32>>();
+        if layout.is_changed() {
+            shift_glyphs(layout.bypass_change_detection(), &shifts);
+        }
+        let Some(children) = children_opt else {
+            continue;
+        };
+        let mut decoration_layouts = decorations.iter_many_mut(children);
+        while let Some(mut decoration_layout) = decoration_layouts.fetch_next() {
+            if decoration_layout.is_changed() {
+                shift_glyphs(decoration_layout.bypass_change_detection(), &shifts);
+            }
+        }
+    }
+}
+
+fn shift_glyphs(layout: &mut TextLayoutInfo, shifts: &[f32]) {
+    for glyph in layout.glyphs.iter_mut() {
+        let shift = shifts.get(glyph.section_index).or(shifts.last());
+        glyph.position.x += shift.copied().unwrap_or_default();
+    }
+}
+
+fn char_metrics(
+    new_word: &str,
+    config: &TypeTextConfig,
+    font_assets: &Assets<Font>,
+    coverage: &mut GlyphCoverage,
+    layout: PageLayout,
+) -> (Handle<Font>, f32, f32) {
+    let font_size = layout.font_size(config);
+    let font = choice_font(&config.fonts, new_word, coverage).unwrap_or_default();
+    let advance = glyph_advance(&font, new_word, font_size, font_assets);
+    (font, font_size, advance)
+}
+
 pub fn initialize_typing_data(
     last_data: &LastTextData,
     text_box_entity: Entity,
 ) -> (Option<Entity>, Option<Entity>, f32, f32, TypingTimer) {
     let last_line_data_opt = last_data.line.iter().find(|x| x.3.get() == text_box_entity);
     let last_line_opt = last_line_data_opt.map(|x| x.0);
-    let last_text_data_opt = last_data
-        .text
+    let line_text_data_opt = last_data
+        .line_text
         .iter()
-        .find(|x| Some(x.4.get()) == last_line_opt);
-    let last_text_opt = last_text_data_opt.map(|x| x.0);
+        .find(|x| Some(x.2.get()) == last_line_opt);
+    let line_text_opt = line_text_data_opt.map(|x| x.0);
     let last_timer = TypingTimer {
         timer: Timer::from_seconds(
-            last_text_data_opt
-                .map(|x| x.3.timer.remaining_secs())
+            line_text_data_opt
+                .map(|x| x.1.remaining_secs())
                 .unwrap_or_default(),
             TimerMode::Once,
         ),
     };
-    let last_x = last_text_data_opt
-        .map(|t| t.1.translation.x)
-        .unwrap_or_default();
+    let last_x = line_text_data_opt.map(|t| t.1.pen()).unwrap_or_default();
     let last_y = last_line_data_opt
         .map(|l| l.1.translation.y)
         .unwrap_or_default();
-    (last_line_opt, last_text_opt, last_x, last_y, last_timer)
+    (last_line_opt, line_text_opt, last_x, last_y, last_timer)
+}
+
+fn append_glyph(
+    world: &mut World,
+    line_text: Entity,
+    section: TextSection,
+    mut glyph: LineGlyph,
+    next_sec: f32,
+) {
+    if let Some(mut lg) = world.get_mut::<LineGlyphs>(line_text) {
+        glyph.reveal_at += lg.elapsed;
+        lg.next_at = lg.elapsed + next_sec;
+        lg.glyphs.push(glyph);
+    }
+    if let Some(mut text) = world.get_mut::<Text>(line_text) {
+        text.sections.push(section);
+    }
 }
 
 fn send_feed_event(
@@ -200,13 +791,10 @@ fn send_feed_event(
     entity: Entity,
     last_timer: &TypingTimer,
     ws: &mut WindowState,
-    last_x: f32,
-    last_y: f32,
 ) {
     fw_event.send(FeedWaitingEvent {
         target_window: entity,
         wait_sec: last_timer.timer.remaining_secs(),
-        last_pos: Vec2::new(last_x, last_y),
     });
     *ws = WindowState::Waiting;
 }
@@ -226,55 +814,168 @@ fn get_next_order(
 
 //Todo: カーニングつける。
 fn make_new_text(
-    new_word: char,
+    new_word: &str,
+    gap: f32,
     config: &TypeTextConfig,
     last_x: &mut f32,
     last_y: f32,
     last_timer: &mut TypingTimer,
     font_assets: &Assets<Font>,
-    max_width: f32,
-) -> Option<CharBundle> {
-    let next_x = *last_x + config.text_style.font_size;
-    if next_x > max_width {
-        None
-    } else {
-        let text_style = TextStyle {
-            font: choice_font(&config.fonts, new_word, font_assets).unwrap_or_default(),
-            ..config.text_style
-        };
-        let text2d_bundle = Text2dBundle {
-            text: Text::from_section(new_word.to_string(), text_style),
-            transform: Transform::from_translation(Vec3::new(next_x, 0., 1.)),
+    coverage: &mut GlyphCoverage,
+    layout: PageLayout,
+) -> Option<NewGlyph> {
+    let (font, font_size, advance) = char_metrics(new_word, config, font_assets, coverage, layout);
+    if *last_x + gap + advance > layout.max_width {
+        return None;
+    }
+    *last_x += gap;
+    let last_secs = last_timer.timer.remaining_secs();
+    let type_sec = match config.typing_timing {
+        TypingTiming::ByChar { sec: s } => last_secs + s,
+        TypingTiming::ByLine { sec: s } => {
+            let is_first_char = layout.is_first_line(config, last_y);
+            last_secs
+                + if *last_x == 0. && !is_first_char {
+                    s
+                } else {
+                    0.
+                }
+        }
+        TypingTiming::ByPage { sec: s } => {
+            let is_first_line = layout.is_first_line(config, last_y);
+            last_secs
+                + if *last_x == 0. && is_first_line {
+                    s
+                } else {
+                    0.
+                }
+        }
+    };
+    let pause_sec = match config.typing_timing {
+        TypingTiming::ByChar { .. } => config
+            .punctuation_pauses
+            .get(&new_word.chars().next().unwrap_or_default())
+            .copied()
+            .unwrap_or_default(),
+        _ => 0.,
+    };
+    let is_batched = matches!(
+        config.writing,
+        WritingStyle::Put | WritingStyle::Fade { .. }
+    ) && config.effects.iter().all(|e| *e == TextEffect::None);
+    let text_style = TextStyle {
+        font,
+        font_size,
+        ..config.text_style
+    };
+    let alpha = text_style.color.a();
+    let separated = (!is_batched).then(|| CharBundle {
+        text_char: MessageTextChar,
+        timer: TypingTimer {
+            timer: Timer::from_seconds(type_sec, TimerMode::Once),
+        },
+        text2d: Text2dBundle {
+            text: Text::from_section(new_word.to_string(), text_style.clone()),
+            transform: Transform::from_translation(Vec3::new(*last_x, 0., 2.)),
             visibility: Visibility::Hidden,
             text_anchor: Anchor::BottomLeft,
             ..default()
+        },
+        layer: config.layer,
+        writing: config.writing,
+        effects: TextEffects::new(config.effects.clone()),
+    });
+    let section = TextSection {
+        value: new_word.to_string(),
+        style: TextStyle {
+            color: text_style.color.with_a(0.),
+            ..text_style
+        },
+    };
+    let glyph = LineGlyph {
+        x: *last_x,
+        advance,
+        font_size,
+        alpha,
+        reveal_at: type_sec,
+        writing: config.writing,
+        reveal: GlyphReveal::Waiting,
+        is_separated: !is_batched,
+        blip: None,
+    };
+    *last_x += advance;
+    *last_timer = TypingTimer {
+        timer: Timer::from_seconds(type_sec + pause_sec, TimerMode::Once),
+    };
+    Some(NewGlyph {
+        section,
+        glyph,
+        separated,
+    })
+}
+
+//Todo: 画像ごとの大きさ指定をつける。
+fn make_inline_image(
+    glyph: &LineGlyph,
+    config: &TypeTextConfig,
+    asset_server: &AssetServer,
+    path: String,
+) -> ImageBundle {
+    let cell = Vec2::new(glyph.advance, glyph.font_size);
+    ImageBundle {
+        text_char: MessageTextChar,
+        inline_image: InlineImage { cell },
+        timer: TypingTimer {
+            timer: Timer::from_seconds(glyph.reveal_at, TimerMode::Once),
+        },
+        sprite: SpriteBundle {
+            sprite: Sprite {
+                color: Color::WHITE.with_a(glyph.alpha),
+                custom_size: Some(cell),
+                ..default()
+            },
+            texture: asset_server.load(path),
+            transform: Transform::from_translation((Vec2::new(glyph.x, 0.) + cell / 2.).extend(2.)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        layer: config.layer,
+        writing: config.writing,
+    }
+}
+
+// 読み込みが終わったら縦横比を保ったまま枠に収める。
+pub fn fit_inline_images(
+    mut image_query: Query<(&InlineImage, &Handle<Image>, &mut Sprite)>,
+    images: Res<Assets<Image>>,
+) {
+    for (inline_image, handle, mut sprite) in &mut image_query {
+        let Some(image) = images.get(handle) else {
+            continue;
         };
-        let last_secs = last_timer.timer.remaining_secs();
-        let type_sec = match config.typing_timing {
-            TypingTiming::ByChar { sec: s } => last_secs + s,
-            TypingTiming::ByLine { sec: s } => {
-                let is_first_char = last_y >= -config.text_style.font_size;
-                last_secs
-                    + if *last_x == 0. && !is_first_char {
-                        s
-                    } else {
-                        0.
-                    }
-            }
-            _ => 0.,
-        };
-        let typing_timer = TypingTimer {
-            timer: Timer::from_seconds(type_sec, TimerMode::Once),
-        };
-        *last_x += config.text_style.font_size;
-        *last_timer = typing_timer.clone();
-        Some(CharBundle {
-            text_char: MessageTextChar,
-            timer: typing_timer,
-            text2d: text2d_bundle,
-            layer: config.layer,
-            writing: config.writing,
-        })
+        let size = image.size();
+        let scale = (inline_image.cell / size).min_element();
+        let fitted = Some(size * scale);
+        if sprite.custom_size != fitted {
+            sprite.custom_size = fitted;
+        }
+    }
+}
+
+// 最後に触った機器が変わったら、表示中のボタン表示も差し替える。
+pub fn update_button_prompts(
+    mut prompt_query: Query<(&ButtonPrompt, &mut Handle<Image>)>,
+    button_icons: Res<ButtonIconSets>,
+    last_device: Res<LastInputDevice>,
+    asset_server: Res<AssetServer>,
+) {
+    if !last_device.is_changed() && !button_icons.is_changed() {
+        return;
+    }
+    for (prompt, mut handle) in &mut prompt_query {
+        if let Some(path) = button_icons.path(&prompt.action, *last_device) {
+            *handle = asset_server.load(path);
+        }
     }
 }
 
@@ -283,11 +984,12 @@ fn make_empty_line(
     config: &TypeTextConfig,
     last_x: &mut f32,
     last_y: &mut f32,
-    min_height: f32,
-) -> Option<LineBundle> {
+    layout: PageLayout,
+    gaps: Vec<f32>,
+) -> Option<(LineBundle, LineTextBundle)> {
     *last_x = 0.;
-    *last_y -= config.text_style.font_size;
-    if *last_y < -min_height {
+    *last_y -= layout.font_size(config);
+    if *last_y < -layout.max_height {
         None
     } else {
         let sprite_bundle = SpriteBundle {
@@ -298,12 +1000,26 @@ fn make_empty_line(
             transform: Transform::from_translation(Vec3::new(0., *last_y, 0.)),
             ..default()
         };
-        Some(LineBundle {
-            sprites: sprite_bundle,
-            line: MessageTextLine {
-                alignment: config.alignment,
+        let line_text_bundle = LineTextBundle {
+            line_text: LineText,
+            glyphs: LineGlyphs { gaps, ..default() },
+            text2d: Text2dBundle {
+                text: Text::from_sections(vec![]),
+                transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
+                text_anchor: Anchor::BottomLeft,
+                ..default()
             },
-        })
+            layer: config.layer,
+        };
+        Some((
+            LineBundle {
+                sprites: sprite_bundle,
+                line: MessageTextLine {
+                    alignment: config.alignment,
+                },
+            },
+            line_text_bundle,
+        ))
     }
 }
 
@@ -318,8 +1034,8 @@ pub fn settle_lines(
         ),
         Without<TextBox>,
     >,
-    text_char: Query<&Text, With<MessageTextChar>>,
-    text_box_query: Query<(&Sprite, &TypeTextConfig, &Parent), With<TextBox>>,
+    line_text_query: Query<&LineGlyphs, With<LineText>>,
+    text_box_query: Query<(&Sprite, &TypeTextConfig, &Parent, Option<&PageFit>), With<TextBox>>,
     window_query: Query<&WindowState>,
 ) {
     let mut sorted = targets.iter_mut().collect::<Vec<(
@@ -330,26 +1046,23 @@ pub fn settle_lines(
         &Parent,
     )>>();
     sorted.sort_by(|a, b| b.1.translation.y.partial_cmp(&a.1.translation.y).unwrap());
-    let mut prev_height = 0f32;
+    let mut prev_heights: HashMap<Entity, f32> = HashMap::new();
     for (mtl, ref mut l_tf, ref mut sprite, children, parent) in sorted.iter_mut() {
-        let text_size_list: Vec<f32> = text_char
-            .iter_many(*children)
-            .map(|c| {
-                c.sections
-                    .first()
-                    .map(|t| t.style.font_size)
-                    .unwrap_or_default()
-            })
-            .collect();
+        let glyphs_opt = line_text_query.iter_many(*children).next();
         let text_box = text_box_query.get(parent.get()).ok();
         let window = text_box.and_then(|x| window_query.get(x.2.get()).ok());
         let base_hight = sprite.custom_size.map(|x| x.y).unwrap_or_default();
-        let line_width: f32 = text_size_list.iter().sum();
-        let line_hight = text_size_list
-            .into_iter()
-            .reduce(|x, y| if x > y { x } else { y })
+        let line_width = glyphs_opt.map(|g| g.pen()).unwrap_or_default();
+        let line_hight = glyphs_opt
+            .and_then(|g| g.line_height())
             .unwrap_or(base_hight);
-        prev_height -= line_hight;
+        let prev_height = prev_heights.entry(parent.get()).or_insert_with(|| {
+            -text_box
+                .and_then(|b| b.3)
+                .map(|f| f.offset_y)
+                .unwrap_or_default()
+        });
+        *prev_height -= line_hight;
         sprite.custom_size = Some(Vec2::new(line_width, line_hight));
         let box_width = text_box
             .and_then(|b| b.0.custom_size.map(|s| s.x))
@@ -360,7 +1073,7 @@ pub fn settle_lines(
             _ => 0.,
         };
         if let Some(WindowState::Typing) = window {
-            l_tf.translation.y = prev_height
+            l_tf.translation.y = *prev_height
         };
     }
 }
@@ -0,0 +1,80 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use super::super::*;
+
+#[derive(Component, Debug)]
+pub struct TextDecoration {
+    pub color: Color,
+}
+
+const OUTLINE_DIRECTIONS: [Vec2; 8] = [
+    Vec2::new(1., 0.),
+    Vec2::new(1., 1.),
+    Vec2::new(0., 1.),
+    Vec2::new(-1., 1.),
+    Vec2::new(-1., 0.),
+    Vec2::new(-1., -1.),
+    Vec2::new(0., -1.),
+    Vec2::new(1., -1.),
+];
+
+// 縁取りと影は、元の文字をずらして重ねた子エンティティで描く。
+// 子なので表示・拡大・移動は親に付いていき、文字と透明度は毎フレーム親から写す。
+pub fn spawn_text_decorations(commands: &mut Commands, target: Entity, config: &TypeTextConfig) {
+    let mut decorations = vec![];
+    if let Some(TextShadow {
+        color: c,
+        offset: o,
+    }) = config.shadow
+    {
+        decorations.push((c, o.extend(-0.2)));
+    }
+    if let Some(TextOutline {
+        color: c,
+        thickness: t,
+    }) = config.outline
+    {
+        for direction in OUTLINE_DIRECTIONS {
+            decorations.push((c, (direction.normalize() * t).extend(-0.1)));
+        }
+    }
+    for (color, translation) in decorations {
+        let decoration = commands
+            .spawn((
+                Text2dBundle {
+                    transform: Transform::from_translation(translation),
+                    text_anchor: Anchor::BottomLeft,
+                    ..default()
+                },
+                TextDecoration { color },
+                config.layer,
+            ))
+            .id();
+        commands.entity(target).add_child(decoration);
+    }
+}
+
+pub fn sync_text_decorations(
+    mut decorations: Query<(&TextDecoration, &Parent, &mut Text)>,
+    originals: Query<Ref<Text>, Without<TextDecoration>>,
+) {
+    for (td, parent, mut text) in &mut decorations {
+        let Ok(original) = originals.get(parent.get()) else {
+            continue;
+        };
+        if !original.is_changed() && !text.is_added() {
+            continue;
+        }
+        text.sections = original
+            .sections
+            .iter()
+            .map(|s| TextSection {
+                value: s.value.clone(),
+                style: TextStyle {
+                    color: td.color.with_a(td.color.a() * s.style.color.a()),
+                    ..s.style.clone()
+                },
+            })
+            .collect();
+    }
+}
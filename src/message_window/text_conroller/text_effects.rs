@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use rand::Rng;
+use std::f32::consts::TAU;
+
+use super::super::*;
+
+#[derive(Component, Debug, Default)]
+pub struct TextEffects {
+    pub effects: Vec<TextEffect>,
+    applied_offset: Vec3,
+    base_colors: Vec<Color>,
+}
+
+impl TextEffects {
+    pub fn new(effects: Vec<TextEffect>) -> Self {
+        TextEffects {
+            effects,
+            ..default()
+        }
+    }
+}
+
+// エフェクトはPostUpdateでだけ乗せて、次のフレームの頭で外す。
+// Update中の各システムは素のTransformと色を見られる。
+pub fn apply_text_effects(
+    mut targets: Query<(&mut TextEffects, &mut Transform, &mut Text)>,
+    time: Res<Time>,
+) {
+    let elapsed = time.elapsed_seconds();
+    let mut rng = rand::thread_rng();
+    for (mut te, mut tf, mut text) in &mut targets {
+        if te.effects.is_empty() {
+            continue;
+        }
+        let base_x = tf.translation.x;
+        let mut offset = Vec3::ZERO;
+        let mut hue_opt = None;
+        for effect in te.effects.iter() {
+            match *effect {
+                TextEffect::Shake { amp: a } => {
+                    let a = a.abs();
+                    offset.x += rng.gen_range(-a..=a);
+                    offset.y += rng.gen_range(-a..=a);
+                }
+                TextEffect::Wave {
+                    amp: a,
+                    speed: s,
+                    length: l,
+                } => {
+                    offset.y += a * (TAU * (elapsed * s + base_x / l)).sin();
+                }
+                TextEffect::Rainbow {
+                    speed: s,
+                    length: l,
+                } => {
+                    hue_opt = Some((elapsed * s + base_x / l).rem_euclid(1.) * 360.);
+                }
+                TextEffect::None => (),
+            }
+        }
+        tf.translation += offset;
+        te.applied_offset = offset;
+        if let Some(hue) = hue_opt {
+            te.base_colors = text.sections.iter().map(|s| s.style.color).collect();
+            for section in text.sections.iter_mut() {
+                let alpha = section.style.color.a();
+                section.style.color = Color::hsla(hue, 0.8, 0.7, alpha);
+            }
+        }
+    }
+}
+
+pub fn reset_text_effects(mut targets: Query<(&mut TextEffects, &mut Transform, &mut Text)>) {
+    for (mut te, mut tf, mut text) in &mut targets {
+        if te.applied_offset != Vec3::ZERO {
+            tf.translation -= te.applied_offset;
+            te.applied_offset = Vec3::ZERO;
+        }
+        if !te.base_colors.is_empty() {
+            let colors = std::mem::take(&mut te.base_colors);
+            for (section, color) in text.sections.iter_mut().zip(colors) {
+                section.style.color = color;
+            }
+        }
+    }
+}
@@ -1,13 +1,44 @@
 use bevy::prelude::*;
 
 use super::super::*;
+use crate::utility::random_char;
 
 #[derive(Component, Debug)]
 pub enum TypingStyle {
-    Wiping { wipe_per_sec: f32 },
+    Wiping {
+        wipe_per_sec: f32,
+    },
+    Fading {
+        fade_per_sec: f32,
+        alpha: f32,
+        progress: f32,
+    },
+    Dropping {
+        drop_per_sec: f32,
+        height: f32,
+        base_y: f32,
+        progress: f32,
+    },
+    Popping {
+        pop_per_sec: f32,
+        overshoot: f32,
+        progress: f32,
+    },
+    Sliding {
+        slide_per_sec: f32,
+        distance: f32,
+        base_x: f32,
+        progress: f32,
+    },
+    Scrambling {
+        timer: Timer,
+        interval: Timer,
+        original: String,
+    },
     Typed,
 }
 
+#[allow(clippy::type_complexity)]
 pub fn trigger_type_animation(
     mut commands: Commands,
     mut untriggered: Query<
@@ -17,30 +48,179 @@ pub fn trigger_type_animation(
             &mut Transform,
             &WritingStyle,
             &mut Visibility,
+            Option<&mut Text>,
+            Option<&mut Sprite>,
         ),
-        Without<TypingStyle>,
+        (Without<TypingStyle>, Without<Paused>),
     >,
     time: Res<Time>,
 ) {
-    for (entity, mut timer, mut tf, w_style, mut visibility) in &mut untriggered {
+    for (entity, mut timer, mut tf, w_style, mut visibility, text_opt, sprite_opt) in
+        &mut untriggered
+    {
         if timer.timer.tick(time.delta()).finished() {
-            match w_style {
+            let typing_style = match *w_style {
                 WritingStyle::Wipe { sec: s } => {
                     tf.scale = Vec3::new(0., 1., 1.);
-                    commands.entity(entity).insert(TypingStyle::Wiping {
+                    Some(TypingStyle::Wiping {
                         wipe_per_sec: 1.0 / s,
-                    });
+                    })
                 }
-                WritingStyle::Put => (),
+                WritingStyle::Put => None,
+                WritingStyle::Fade { sec: s } => {
+                    let alpha = match (text_opt, sprite_opt) {
+                        (Some(mut text), _) => {
+                            let alpha = text
+                                .sections
+                                .first()
+                                .map(|t| t.style.color.a())
+                                .unwrap_or(1.);
+                            set_text_alpha(&mut text, 0.);
+                            Some(alpha)
+                        }
+                        (None, Some(mut sprite)) => {
+                            let alpha = sprite.color.a();
+                            sprite.color.set_a(0.);
+                            Some(alpha)
+                        }
+                        _ => None,
+                    };
+                    alpha.map(|a| TypingStyle::Fading {
+                        fade_per_sec: 1.0 / s,
+                        alpha: a,
+                        progress: 0.,
+                    })
+                }
+                WritingStyle::Drop { sec: s, height: h } => {
+                    let base_y = tf.translation.y;
+                    tf.translation.y += h;
+                    Some(TypingStyle::Dropping {
+                        drop_per_sec: 1.0 / s,
+                        height: h,
+                        base_y,
+                        progress: 0.,
+                    })
+                }
+                WritingStyle::Pop {
+                    sec: s,
+                    overshoot: o,
+                } => {
+                    tf.scale = Vec3::new(0., 0., 1.);
+                    Some(TypingStyle::Popping {
+                        pop_per_sec: 1.0 / s,
+                        overshoot: o,
+                        progress: 0.,
+                    })
+                }
+                WritingStyle::Slide {
+                    sec: s,
+                    distance: d,
+                } => {
+                    let base_x = tf.translation.x;
+                    tf.translation.x += d;
+                    Some(TypingStyle::Sliding {
+                        slide_per_sec: 1.0 / s,
+                        distance: d,
+                        base_x,
+                        progress: 0.,
+                    })
+                }
+                WritingStyle::Scramble {
+                    sec: s,
+                    interval: i,
+                } => text_opt.and_then(|mut text| {
+                    let section = text.sections.first_mut()?;
+                    let original = section.value.clone();
+                    section.value = random_char().map(String::from).unwrap_or_default();
+                    Some(TypingStyle::Scrambling {
+                        timer: Timer::from_seconds(s, TimerMode::Once),
+                        interval: Timer::from_seconds(i, TimerMode::Repeating),
+                        original,
+                    })
+                }),
+            };
+            if let Some(ts) = typing_style {
+                commands.entity(entity).insert(ts);
             }
             *visibility = Visibility::Inherited;
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
+pub fn reveal_line_glyphs(
+    mut line_texts: Query<(&mut LineGlyphs, &mut Text), (With<LineText>, Without<Paused>)>,
+    time: Res<Time>,
+) {
+    for (mut lg, mut text) in &mut line_texts {
+        let LineGlyphs {
+            glyphs,
+            cursor,
+            elapsed,
+            ..
+        } = &mut *lg;
+        *elapsed += time.delta_seconds();
+        let is_fading = glyphs[..*cursor]
+            .iter()
+            .any(|g| matches!(g.reveal, GlyphReveal::Fading { .. }));
+        let is_arrived = glyphs
+            .get(*cursor)
+            .map(|g| g.reveal_at <= *elapsed)
+            .unwrap_or(false);
+        if !is_fading && !is_arrived {
+            continue;
+        }
+        while let Some(glyph) = glyphs.get_mut(*cursor) {
+            if glyph.reveal_at > *elapsed {
+                break;
+            }
+            glyph.reveal = match glyph.writing {
+                WritingStyle::Fade { sec: s } if !glyph.is_separated => GlyphReveal::Fading {
+                    fade_per_sec: 1.0 / s,
+                    progress: 0.,
+                },
+                _ => GlyphReveal::Typed,
+            };
+            if !glyph.is_separated && glyph.reveal == GlyphReveal::Typed {
+                if let Some(section) = text.sections.get_mut(*cursor) {
+                    section.style.color.set_a(glyph.alpha);
+                }
+            }
+            *cursor += 1;
+        }
+        for (glyph, section) in glyphs.iter_mut().zip(text.sections.iter_mut()) {
+            if let GlyphReveal::Fading {
+                fade_per_sec: sec,
+                progress: p,
+            } = &mut glyph.reveal
+            {
+                *p += time.delta_seconds() * *sec;
+                if *p >= 1. {
+                    section.style.color.set_a(glyph.alpha);
+                    glyph.reveal = GlyphReveal::Typed;
+                } else {
+                    section.style.color.set_a(glyph.alpha * *p);
+                }
+            }
+        }
+    }
+}
+
+pub fn complete_line_glyphs(lg: &mut LineGlyphs, text: &mut Text) {
+    for (glyph, section) in lg.glyphs.iter_mut().zip(text.sections.iter_mut()) {
+        if !glyph.is_separated {
+            section.style.color.set_a(glyph.alpha);
+        }
+        glyph.reveal = GlyphReveal::Typed;
+        glyph.blip = None;
+    }
+    lg.cursor = lg.glyphs.len();
+    lg.elapsed = lg.elapsed.max(lg.next_at);
+}
+
 pub fn text_wipe(
     mut commands: Commands,
-    mut target: Query<(Entity, &TypingStyle, &mut Transform)>,
+    mut target: Query<(Entity, &TypingStyle, &mut Transform), Without<Paused>>,
     time: Res<Time>,
 ) {
     for (entity, ts, mut tf) in &mut target {
@@ -54,3 +234,176 @@ pub fn text_wipe(
         }
     }
 }
+
+#[allow(clippy::type_complexity)]
+pub fn text_fade(
+    mut commands: Commands,
+    mut target: Query<
+        (
+            Entity,
+            &mut TypingStyle,
+            Option<&mut Text>,
+            Option<&mut Sprite>,
+        ),
+        Without<Paused>,
+    >,
+    time: Res<Time>,
+) {
+    for (entity, mut ts, mut text_opt, mut sprite_opt) in &mut target {
+        if let TypingStyle::Fading {
+            fade_per_sec: sec,
+            alpha: a,
+            progress: p,
+        } = &mut *ts
+        {
+            *p += time.delta_seconds() * *sec;
+            let alpha = if *p >= 1. {
+                commands.entity(entity).insert(TypingStyle::Typed);
+                *a
+            } else {
+                *a * *p
+            };
+            if let Some(text) = text_opt.as_deref_mut() {
+                set_text_alpha(text, alpha);
+            }
+            if let Some(sprite) = sprite_opt.as_deref_mut() {
+                sprite.color.set_a(alpha);
+            }
+        }
+    }
+}
+
+pub fn text_drop(
+    mut commands: Commands,
+    mut target: Query<(Entity, &mut TypingStyle, &mut Transform), Without<Paused>>,
+    time: Res<Time>,
+) {
+    for (entity, mut ts, mut tf) in &mut target {
+        if let TypingStyle::Dropping {
+            drop_per_sec: sec,
+            height: h,
+            base_y: y,
+            progress: p,
+        } = &mut *ts
+        {
+            *p += time.delta_seconds() * *sec;
+            if *p >= 1. {
+                tf.translation.y = *y;
+                commands.entity(entity).insert(TypingStyle::Typed);
+            } else {
+                tf.translation.y = *y + *h * (1. - ease_out(*p));
+            }
+        }
+    }
+}
+
+pub fn text_pop(
+    mut commands: Commands,
+    mut target: Query<(Entity, &mut TypingStyle, &mut Transform), Without<Paused>>,
+    time: Res<Time>,
+) {
+    for (entity, mut ts, mut tf) in &mut target {
+        if let TypingStyle::Popping {
+            pop_per_sec: sec,
+            overshoot: o,
+            progress: p,
+        } = &mut *ts
+        {
+            *p += time.delta_seconds() * *sec;
+            if *p >= 1. {
+                tf.scale = Vec3::ONE;
+                commands.entity(entity).insert(TypingStyle::Typed);
+            } else {
+                let scale = ease_out_back(*p, *o);
+                tf.scale = Vec3::new(scale, scale, 1.);
+            }
+        }
+    }
+}
+
+pub fn text_slide(
+    mut commands: Commands,
+    mut target: Query<(Entity, &mut TypingStyle, &mut Transform), Without<Paused>>,
+    time: Res<Time>,
+) {
+    for (entity, mut ts, mut tf) in &mut target {
+        if let TypingStyle::Sliding {
+            slide_per_sec: sec,
+            distance: d,
+            base_x: x,
+            progress: p,
+        } = &mut *ts
+        {
+            *p += time.delta_seconds() * *sec;
+            if *p >= 1. {
+                tf.translation.x = *x;
+                commands.entity(entity).insert(TypingStyle::Typed);
+            } else {
+                tf.translation.x = *x + *d * (1. - ease_out(*p));
+            }
+        }
+    }
+}
+
+pub fn text_scramble(
+    mut commands: Commands,
+    mut target: Query<(Entity, &mut TypingStyle, &mut Text), Without<Paused>>,
+    time: Res<Time>,
+) {
+    for (entity, mut ts, mut text) in &mut target {
+        if let TypingStyle::Scrambling {
+            timer: t,
+            interval: i,
+            original: o,
+        } = &mut *ts
+        {
+            let Some(section) = text.sections.first_mut() else {
+                continue;
+            };
+            if t.tick(time.delta()).finished() {
+                section.value = o.clone();
+                commands.entity(entity).insert(TypingStyle::Typed);
+            } else if i.tick(time.delta()).just_finished() {
+                section.value = random_char().map(String::from).unwrap_or_default();
+            }
+        }
+    }
+}
+
+pub fn complete_typing(
+    ts: &TypingStyle,
+    tf: &mut Transform,
+    text_opt: Option<&mut Text>,
+    sprite_opt: Option<&mut Sprite>,
+) {
+    match (ts, text_opt, sprite_opt) {
+        (TypingStyle::Fading { alpha: a, .. }, Some(text), _) => set_text_alpha(text, *a),
+        (TypingStyle::Fading { alpha: a, .. }, None, Some(sprite)) => {
+            sprite.color.set_a(*a);
+        }
+        (TypingStyle::Dropping { base_y: y, .. }, ..) => tf.translation.y = *y,
+        (TypingStyle::Sliding { base_x: x, .. }, ..) => tf.translation.x = *x,
+        (TypingStyle::Scrambling { original: o, .. }, Some(text), _) => {
+            if let Some(section) = text.sections.first_mut() {
+                section.value = o.clone();
+            }
+        }
+        _ => (),
+    }
+    tf.scale = Vec3::ONE;
+}
+
+fn set_text_alpha(text: &mut Text, alpha: f32) {
+    for section in text.sections.iter_mut() {
+        section.style.color.set_a(alpha);
+    }
+}
+
+fn ease_out(t: f32) -> f32 {
+    1. - (1. - t).powi(2)
+}
+
+fn ease_out_back(t: f32, overshoot: f32) -> f32 {
+    let u = t - 1.;
+    1. + (overshoot + 1.) * u.powi(3) + overshoot * u.powi(2)
+}
@@ -0,0 +1,98 @@
+use bevy::{
+    audio::{AudioSink, AudioSinkPlayback, Volume},
+    prelude::*,
+    utils::HashSet,
+};
+use rand::Rng;
+
+use super::super::*;
+
+#[derive(Debug)]
+pub struct BlipSound {
+    pub path: String,
+    pub speed: f32,
+    pub volume: f32,
+    pub every: usize,
+}
+
+#[derive(Component, Debug)]
+pub struct TypingBlip {
+    pub text_box: Entity,
+}
+
+pub fn make_blip(new_word: &str, config: &TypeTextConfig) -> Option<BlipSound> {
+    let voice = config.voice.as_ref()?;
+    let new_word = new_word.chars().next()?;
+    if (voice.skip_whitespace && new_word.is_whitespace())
+        || (voice.skip_punctuation && is_punctuation(new_word))
+    {
+        return None;
+    }
+    let path = get_random(&voice.sound_paths)?.clone();
+    let variation = voice.pitch_variation.abs();
+    Some(BlipSound {
+        path,
+        speed: 1. + rand::thread_rng().gen_range(-variation..=variation),
+        volume: voice.volume,
+        every: voice.every,
+    })
+}
+
+pub fn play_typing_blips(
+    mut commands: Commands,
+    mut line_text_query: Query<(&mut LineGlyphs, &Parent), With<LineText>>,
+    line_query: Query<&Parent, With<MessageTextLine>>,
+    mut counter_query: Query<&mut BlipCounter>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut played = HashSet::new();
+    for (mut lg, parent) in &mut line_text_query {
+        let LineGlyphs { glyphs, cursor, .. } = &mut *lg;
+        let Ok(tb_entity) = line_query.get(parent.get()).map(|p| p.get()) else {
+            continue;
+        };
+        let Ok(mut counter) = counter_query.get_mut(tb_entity) else {
+            continue;
+        };
+        for blip in glyphs[..*cursor].iter_mut().filter_map(|g| g.blip.take()) {
+            let is_turn = counter.count % blip.every.max(1) == 0;
+            counter.count += 1;
+            if is_turn && played.insert(tb_entity) {
+                commands.spawn((
+                    AudioBundle {
+                        source: asset_server.load(&blip.path),
+                        settings: PlaybackSettings::DESPAWN
+                            .with_speed(blip.speed)
+                            .with_volume(Volume::new_relative(blip.volume)),
+                    },
+                    TypingBlip {
+                        text_box: tb_entity,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+pub fn stop_typing_blips(
+    mut commands: Commands,
+    blip_query: Query<(Entity, &TypingBlip, Option<&AudioSink>)>,
+    mut bms_reader: EventReader<BMSEvent>,
+) {
+    for event_wrapper in bms_reader.iter() {
+        if let Some(InputForSkipping {
+            target_text_box: Some(tb_entity),
+            ..
+        }) = event_wrapper.get_opt::<InputForSkipping>()
+        {
+            for (blip_entity, blip, sink_opt) in &blip_query {
+                if blip.text_box == tb_entity {
+                    if let Some(sink) = sink_opt {
+                        sink.stop();
+                    }
+                    commands.entity(blip_entity).despawn();
+                }
+            }
+        }
+    }
+}
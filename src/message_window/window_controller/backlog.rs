@@ -0,0 +1,246 @@
+use bevy::{
+    input::mouse::MouseWheel,
+    prelude::*,
+    render::view::RenderLayers,
+    sprite::Anchor,
+    text::{Text2dBounds, TextSection},
+};
+
+use super::*;
+
+#[derive(Debug, Clone)]
+pub struct BacklogSpan {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+#[derive(Debug, Clone)]
+pub struct BacklogEntry {
+    pub speaker: String,
+    pub lines: Vec<Vec<BacklogSpan>>,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Backlog {
+    pub entries: Vec<BacklogEntry>,
+}
+
+impl Backlog {
+    pub fn push_page(&mut self, speaker: &str, lines: Vec<Vec<BacklogSpan>>) {
+        if lines.iter().all(|l| l.is_empty()) {
+            return;
+        }
+        self.entries.push(BacklogEntry {
+            speaker: speaker.to_string(),
+            lines,
+        });
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|e| e.lines.len() + usize::from(!e.speaker.is_empty()))
+            .sum()
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct BacklogConfig {
+    pub size: Vec2,
+    pub visible_lines: usize,
+    pub background_color: Color,
+    pub open_keys: Vec<KeyCode>,
+    pub close_keys: Vec<KeyCode>,
+}
+
+impl Default for BacklogConfig {
+    fn default() -> Self {
+        BacklogConfig {
+            size: Vec2::new(1100., 600.),
+            visible_lines: 14,
+            background_color: Color::BLACK.with_a(0.8),
+            open_keys: vec![KeyCode::L],
+            close_keys: vec![KeyCode::Escape, KeyCode::L],
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct BacklogViewer {
+    pub scroll: usize,
+}
+
+#[derive(Component, Debug)]
+pub struct BacklogText;
+
+#[derive(Reflect, Default, Debug)]
+pub struct OpenBacklog;
+
+#[derive(Reflect, Default, Debug)]
+pub struct CloseBacklog;
+
+pub fn open_backlog(
+    mut commands: Commands,
+    viewer_query: Query<Entity, With<BacklogViewer>>,
+    mut bms_reader: EventReader<BMSEvent>,
+    mut wheel_reader: EventReader<MouseWheel>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    backlog_config: Res<BacklogConfig>,
+    setup_config: Res<SetupConfig>,
+) {
+    let is_evented = bms_reader
+        .iter()
+        .any(|e| e.get_opt::<OpenBacklog>().is_some());
+    let is_wheeled = wheel_reader.iter().any(|w| w.y > 0.);
+    let gamepad_open_button = gamepads.iter().next().map(|x| GamepadButton {
+        gamepad: x,
+        button_type: GamepadButtonType::North,
+    });
+    let is_pressed = keys.any_just_pressed(backlog_config.open_keys.iter().copied())
+        || gamepad_open_button.is_some_and(|x| gamepad_buttons.just_pressed(x));
+    if !viewer_query.is_empty() || !(is_evented || is_wheeled || is_pressed) {
+        return;
+    }
+    let layer = RenderLayers::layer(setup_config.render_layer);
+    let Vec2 { x: w, y: h } = backlog_config.size;
+    let background = SpriteBundle {
+        sprite: Sprite {
+            color: backlog_config.background_color,
+            custom_size: Some(backlog_config.size),
+            ..default()
+        },
+        transform: Transform::from_translation(Vec3::new(0., 0., 100.)),
+        ..default()
+    };
+    let text = Text2dBundle {
+        text_anchor: Anchor::TopLeft,
+        text_2d_bounds: Text2dBounds {
+            size: Vec2::new(w - 40., h - 40.),
+        },
+        transform: Transform::from_translation(Vec3::new(20. - w / 2., h / 2. - 20., 1.)),
+        ..default()
+    };
+    let text_entity = commands.spawn((text, BacklogText, layer)).id();
+    commands
+        .spawn((background, BacklogViewer { scroll: 0 }, layer))
+        .add_child(text_entity);
+}
+
+pub fn scroll_backlog(
+    mut viewer_query: Query<&mut BacklogViewer>,
+    mut wheel_reader: EventReader<MouseWheel>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    backlog: Res<Backlog>,
+    backlog_config: Res<BacklogConfig>,
+) {
+    let Ok(mut viewer) = viewer_query.get_single_mut() else {
+        wheel_reader.clear();
+        return;
+    };
+    let gamepad_button = |button_type| {
+        gamepads.iter().next().is_some_and(|x| {
+            gamepad_buttons.just_pressed(GamepadButton {
+                gamepad: x,
+                button_type,
+            })
+        })
+    };
+    let page = backlog_config.visible_lines as isize;
+    let mut step: isize = wheel_reader.iter().map(|w| w.y.signum() as isize).sum();
+    if keys.just_pressed(KeyCode::Up) || gamepad_button(GamepadButtonType::DPadUp) {
+        step += 1;
+    }
+    if keys.just_pressed(KeyCode::Down) || gamepad_button(GamepadButtonType::DPadDown) {
+        step -= 1;
+    }
+    if keys.just_pressed(KeyCode::PageUp) || gamepad_button(GamepadButtonType::LeftTrigger) {
+        step += page;
+    }
+    if keys.just_pressed(KeyCode::PageDown) || gamepad_button(GamepadButtonType::RightTrigger) {
+        step -= page;
+    }
+    if step == 0 {
+        return;
+    }
+    let max_scroll = backlog
+        .line_count()
+        .saturating_sub(backlog_config.visible_lines);
+    viewer.scroll = (viewer.scroll as isize + step).clamp(0, max_scroll as isize) as usize;
+}
+
+pub fn close_backlog(
+    mut commands: Commands,
+    viewer_query: Query<Entity, With<BacklogViewer>>,
+    mut bms_reader: EventReader<BMSEvent>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    backlog_config: Res<BacklogConfig>,
+) {
+    let is_evented = bms_reader
+        .iter()
+        .any(|e| e.get_opt::<CloseBacklog>().is_some());
+    let gamepad_close_button = gamepads.iter().next().map(|x| GamepadButton {
+        gamepad: x,
+        button_type: GamepadButtonType::East,
+    });
+    let is_pressed = keys.any_just_pressed(backlog_config.close_keys.iter().copied())
+        || mouse_buttons.just_pressed(MouseButton::Right)
+        || gamepad_close_button.is_some_and(|x| gamepad_buttons.just_pressed(x));
+    if is_evented || is_pressed {
+        for viewer_entity in &viewer_query {
+            commands.entity(viewer_entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn draw_backlog(
+    viewer_query: Query<(Ref<BacklogViewer>, &Children)>,
+    mut text_query: Query<&mut Text, With<BacklogText>>,
+    backlog: Res<Backlog>,
+    backlog_config: Res<BacklogConfig>,
+) {
+    for (viewer, children) in &viewer_query {
+        if !viewer.is_changed() && !backlog.is_changed() {
+            continue;
+        }
+        let mut lines: Vec<Vec<TextSection>> = vec![];
+        for entry in backlog.entries.iter() {
+            let first_style = entry.lines.iter().flatten().next().map(|s| s.style.clone());
+            if let Some(style) = first_style.filter(|_| !entry.speaker.is_empty()) {
+                lines.push(vec![TextSection::new(
+                    format!("【{}】", entry.speaker),
+                    style,
+                )]);
+            }
+            for line in entry.lines.iter() {
+                lines.push(
+                    line.iter()
+                        .map(|s| TextSection::new(s.text.clone(), s.style.clone()))
+                        .collect(),
+                );
+            }
+        }
+        let end = lines.len().saturating_sub(viewer.scroll);
+        let start = end.saturating_sub(backlog_config.visible_lines);
+        let mut sections: Vec<TextSection> = vec![];
+        for line in lines.drain(start..end) {
+            if let Some(last) = sections.last_mut() {
+                last.value.push('\n');
+            }
+            if line.is_empty() {
+                sections.push(TextSection::new("", TextStyle::default()));
+            }
+            sections.extend(line);
+        }
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections = sections.clone();
+        }
+    }
+}
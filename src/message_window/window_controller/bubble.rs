@@ -0,0 +1,123 @@
+use super::popup::PoppingUp;
+use super::sinkdown::SinkingDown;
+use super::*;
+use crate::message_window::MessageWindowCamera;
+
+#[derive(Component, Debug)]
+pub struct BubbleFrame {
+    pub rect: Rect,
+    pub tail: Entity,
+}
+
+#[derive(Component, Debug)]
+pub struct BubbleTail;
+
+#[allow(clippy::type_complexity)]
+pub fn follow_speakers(
+    mut mw_query: Query<
+        (
+            &SpeechBubble,
+            &BubbleFrame,
+            &mut Transform,
+            Option<&mut PoppingUp>,
+            Option<&mut SinkingDown>,
+        ),
+        With<MessageWindow>,
+    >,
+    mut tail_query: Query<
+        (&mut Transform, &mut Sprite),
+        (With<BubbleTail>, Without<MessageWindow>),
+    >,
+    target_query: Query<&GlobalTransform>,
+    camera_query: Query<(&Camera, &GlobalTransform), Without<MessageWindowCamera>>,
+    mw_camera_query: Query<(&Camera, &GlobalTransform), With<MessageWindowCamera>>,
+) {
+    let Ok((mw_camera, mw_camera_gt)) = mw_camera_query.get_single() else {
+        return;
+    };
+    let Some(screen) = screen_rect(mw_camera, mw_camera_gt) else {
+        return;
+    };
+    for (bubble, frame, mut tf, popping_opt, sinking_opt) in &mut mw_query {
+        let Ok(target_gt) = target_query.get(bubble.target) else {
+            continue;
+        };
+        let camera_opt = match bubble.camera {
+            Some(c) => camera_query.get(c).ok(),
+            None => camera_query.iter().find(|(c, _)| c.is_active),
+        };
+        let Some(anchor) = camera_opt
+            .and_then(|(c, gt)| c.world_to_viewport(gt, target_gt.translation() + bubble.offset))
+            .and_then(|v| mw_camera.viewport_to_world_2d(mw_camera_gt, v))
+        else {
+            continue;
+        };
+        let (origin, is_below) = place_bubble(bubble, frame.rect, anchor, screen);
+        tf.translation = origin.extend(tf.translation.z);
+        // 開閉中の動きも付いていった先を基準にする。
+        if let Some(mut popping) = popping_opt {
+            popping.origin = tf.translation;
+        }
+        if let Some(mut sinking) = sinking_opt {
+            sinking.origin = tf.translation;
+        }
+        if let Ok((mut tail_tf, mut tail_sp)) = tail_query.get_mut(frame.tail) {
+            let local = anchor - origin;
+            let half = bubble.tail_width / 2.;
+            let x = clamp_within(local.x, frame.rect.min.x + half, frame.rect.max.x - half);
+            let (y, length) = if is_below {
+                (frame.rect.max.y, local.y - frame.rect.max.y)
+            } else {
+                (frame.rect.min.y, frame.rect.min.y - local.y)
+            };
+            tail_tf.translation = Vec3::new(x, y, tail_tf.translation.z);
+            tail_sp.anchor = if is_below {
+                Anchor::BottomCenter
+            } else {
+                Anchor::TopCenter
+            };
+            tail_sp.flip_y = is_below;
+            tail_sp.custom_size = Some(Vec2::new(bubble.tail_width, length.max(0.)));
+        }
+    }
+}
+
+fn screen_rect(camera: &Camera, camera_gt: &GlobalTransform) -> Option<Rect> {
+    let size = camera.logical_viewport_size()?;
+    let top_left = camera.viewport_to_world_2d(camera_gt, Vec2::ZERO)?;
+    let bottom_right = camera.viewport_to_world_2d(camera_gt, size)?;
+    Some(Rect::from_corners(top_left, bottom_right))
+}
+
+// 話し手の上に置いて画面からはみ出すなら下に回し、そのうえで画面の内側に収める。
+fn place_bubble(bubble: &SpeechBubble, frame: Rect, anchor: Vec2, screen: Rect) -> (Vec2, bool) {
+    let inner = Rect::new(
+        screen.min.x + bubble.margin,
+        screen.min.y + bubble.margin,
+        screen.max.x - bubble.margin,
+        screen.max.y - bubble.margin,
+    );
+    let above_y = anchor.y + bubble.gap - frame.min.y;
+    let below_y = anchor.y - bubble.gap - frame.max.y;
+    let is_below = above_y + frame.max.y > inner.max.y && below_y + frame.min.y >= inner.min.y;
+    let y = clamp_within(
+        if is_below { below_y } else { above_y },
+        inner.min.y - frame.min.y,
+        inner.max.y - frame.max.y,
+    );
+    let x = clamp_within(
+        anchor.x - frame.center().x,
+        inner.min.x - frame.min.x,
+        inner.max.x - frame.max.x,
+    );
+    (Vec2::new(x, y), is_below)
+}
+
+// 枠が画面より大きいときは真ん中に寄せる。
+fn clamp_within(value: f32, min: f32, max: f32) -> f32 {
+    if min > max {
+        (min + max) / 2.
+    } else {
+        value.clamp(min, max)
+    }
+}
@@ -8,18 +8,37 @@ use bevy::{
     text::TextAlignment,
 };
 
+pub mod backlog;
+pub mod bubble;
+pub mod nine_slice;
+pub mod pause;
+pub mod play_mode;
 pub mod popup;
+pub mod save;
 pub mod sinkdown;
+pub mod theme;
 pub mod waiting;
 
 use super::setup::SetupConfig;
 use crate::read_script::*;
+use std::collections::HashMap;
 
 #[derive(Component, Debug)]
 pub struct MessageWindow {
     pub name: String,
 }
 
+#[derive(Component, Debug, Default)]
+pub struct Speaker {
+    pub name: String,
+}
+
+// スクリプトから書き込む窓ごとの変数。セーブにもそのまま入る。
+#[derive(Component, Debug, Default)]
+pub struct ScriptVariables {
+    pub values: HashMap<String, String>,
+}
+
 #[derive(Component, Debug)]
 pub struct TextBox {
     pub name: String,
@@ -33,29 +52,92 @@ pub struct TypeTextConfig {
     pub fonts: Vec<Handle<Font>>,
     pub text_style: TextStyle,
     pub writing: WritingStyle,
+    pub effects: Vec<TextEffect>,
+    pub voice: Option<TypingVoice>,
     pub typing_timing: TypingTiming,
+    pub punctuation_pauses: HashMap<char, f32>,
+    pub outline: Option<TextOutline>,
+    pub shadow: Option<TextShadow>,
     pub layer: RenderLayers,
     pub alignment: TextAlignment,
+    pub justify: bool,
+    pub style_stack: Vec<TextStyleSnapshot>,
+    pub default_style: TextStyleSnapshot,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextStyleSnapshot {
+    pub fonts: Vec<Handle<Font>>,
+    pub text_style: TextStyle,
+    pub writing: WritingStyle,
+    pub effects: Vec<TextEffect>,
+    pub voice: Option<TypingVoice>,
+}
+
+impl TypeTextConfig {
+    pub fn snapshot(&self) -> TextStyleSnapshot {
+        TextStyleSnapshot {
+            fonts: self.fonts.clone(),
+            text_style: self.text_style.clone(),
+            writing: self.writing,
+            effects: self.effects.clone(),
+            voice: self.voice.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, style: TextStyleSnapshot) {
+        self.fonts = style.fonts;
+        self.text_style = style.text_style;
+        self.writing = style.writing;
+        self.effects = style.effects;
+        self.voice = style.voice;
+    }
+
+    pub fn push_style(&mut self) {
+        let style = self.snapshot();
+        self.style_stack.push(style);
+    }
+
+    pub fn pop_style(&mut self) {
+        if let Some(style) = self.style_stack.pop() {
+            self.restore(style);
+        }
+    }
+
+    pub fn reset_style(&mut self) {
+        self.push_style();
+        self.restore(self.default_style.clone());
+    }
 }
 
 #[derive(Bundle)]
 struct MessageWindowBundle {
     message_window: MessageWindow,
+    speaker: Speaker,
+    variables: ScriptVariables,
     state: WindowState,
     waitting: WaitBrakerStyle,
     script: LoadedScript,
     popup_type: PopupType,
+    sinkdown_type: SinkDownType,
+    play_mode: play_mode::PlayMode,
+    pause: pause::WindowPause,
 }
 
 #[derive(Bundle)]
 struct TextBoxBundle {
     text_box: TextBox,
     feeding: FeedingStyle,
+    overflow: OverflowPolicy,
+    vertical_alignment: VerticalAlignment,
     config: TypeTextConfig,
+    blip_counter: BlipCounter,
+    cursor: ScriptCursor,
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default, Reflect)]
 pub enum WindowState {
+    #[default]
     Preparing,
     PoppingUp,
     Typing,
@@ -65,40 +147,325 @@ pub enum WindowState {
     Fixed,
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
 pub enum PopupType {
-    Scale { sec: f32 },
+    Scale {
+        sec: f32,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    Fade {
+        sec: f32,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    ScaleFade {
+        sec: f32,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    Slide {
+        sec: f32,
+        #[reflect(default)]
+        from: ScreenEdge,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Reflect)]
+pub enum ScreenEdge {
+    #[default]
+    Bottom,
+    Top,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Reflect)]
+pub enum Easing {
+    #[default]
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    OutCubic,
+    OutBack,
 }
 
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::InQuad => t * t,
+            Easing::OutQuad => 1. - (1. - t).powi(2),
+            Easing::InOutQuad => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    1. - (2. - 2. * t).powi(2) / 2.
+                }
+            }
+            Easing::OutCubic => 1. - (1. - t).powi(3),
+            Easing::OutBack => {
+                let overshoot = 1.70158;
+                let u = t - 1.;
+                1. + (overshoot + 1.) * u.powi(3) + overshoot * u.powi(2)
+            }
+        }
+    }
+}
+
+// 閉じるときは開くときの動きを逆に再生する。Dissolveは文字を先に消してから枠を消す。
 #[derive(Component, Debug, Clone, Copy, PartialEq, Default, Reflect)]
 pub enum SinkDownType {
     #[default]
     Fix,
     Scale {
         sec: f32,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    Fade {
+        sec: f32,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    ScaleFade {
+        sec: f32,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    Slide {
+        sec: f32,
+        #[reflect(default)]
+        to: ScreenEdge,
+        #[reflect(default)]
+        easing: Easing,
+    },
+    Dissolve {
+        text_sec: f32,
+        frame_sec: f32,
+        #[reflect(default)]
+        easing: Easing,
     },
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
 pub enum TypingTiming {
     ByChar { sec: f32 },
     ByLine { sec: f32 },
-    ByPage,
+    ByPage { sec: f32 },
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default, Reflect)]
 pub enum WritingStyle {
-    Wipe { sec: f32 },
+    Wipe {
+        sec: f32,
+    },
+    #[default]
     Put,
+    Fade {
+        sec: f32,
+    },
+    Drop {
+        sec: f32,
+        height: f32,
+    },
+    Pop {
+        sec: f32,
+        overshoot: f32,
+    },
+    Slide {
+        sec: f32,
+        distance: f32,
+    },
+    Scramble {
+        sec: f32,
+        interval: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Reflect)]
+pub enum TextEffect {
+    #[default]
+    None,
+    Shake {
+        amp: f32,
+    },
+    Wave {
+        amp: f32,
+        speed: f32,
+        length: f32,
+    },
+    Rainbow {
+        speed: f32,
+        length: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct TextOutline {
+    pub color: Color,
+    pub thickness: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct TextShadow {
+    pub color: Color,
+    pub offset: Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct TypingVoice {
+    pub sound_paths: Vec<String>,
+    pub every: usize,
+    pub pitch_variation: f32,
+    pub volume: f32,
+    pub skip_whitespace: bool,
+    pub skip_punctuation: bool,
+}
+
+impl Default for TypingVoice {
+    fn default() -> Self {
+        TypingVoice {
+            sound_paths: vec![],
+            every: 2,
+            pitch_variation: 0.1,
+            volume: 0.5,
+            skip_whitespace: true,
+            skip_punctuation: true,
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct FontSets {
+    pub fonts: HashMap<String, Vec<String>>,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct VoiceSets {
+    pub voices: HashMap<String, TypingVoice>,
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LastInputDevice {
+    #[default]
+    Keyboard,
+    Mouse,
+    Touch,
+    Gamepad,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ButtonIcon {
+    pub keyboard: String,
+    pub mouse: String,
+    pub gamepad: String,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct ButtonIconSets {
+    pub actions: HashMap<String, ButtonIcon>,
+}
+
+impl ButtonIconSets {
+    // 機器ごとの絵が無ければキーボードの絵で代用する。
+    pub fn path(&self, action: &str, device: LastInputDevice) -> Option<&str> {
+        let icon = self.actions.get(action)?;
+        let path = match device {
+            LastInputDevice::Keyboard => &icon.keyboard,
+            LastInputDevice::Mouse | LastInputDevice::Touch => &icon.mouse,
+            LastInputDevice::Gamepad => &icon.gamepad,
+        };
+        Some(path)
+            .filter(|p| !p.is_empty())
+            .or(Some(&icon.keyboard).filter(|p| !p.is_empty()))
+            .map(String::as_str)
+    }
+}
+
+#[derive(Component, Default, Debug)]
+pub struct BlipCounter {
+    pub count: usize,
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
+// 書きかけの命令はテキストボックスごとに持ち、他の窓と混ざらないようにする。
+// page_ordersとpage_styleは今のページの頭から読んだ命令と、そのときの文字の設定。
+#[derive(Component, Default, Debug)]
+pub struct ScriptCursor {
+    pub pending: Option<Order>,
+    pub in_cr: bool,
+    pub is_finished: bool,
+    pub page_orders: Vec<Order>,
+    pub page_style: Option<(TextStyleSnapshot, Vec<TextStyleSnapshot>)>,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
 pub enum FeedingStyle {
     Scroll { size: usize, sec: f32 },
-    // Fade,
+    Fade { sec: f32 },
+    SlideOut { sec: f32, to: FeedDirection },
+    Clear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub enum FeedDirection {
+    Left,
+    Up,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OverflowPolicy {
+    #[default]
+    Paginate,
+    ShrinkToFit,
+    Ellipsis,
+    Clip,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub enum VerticalAlignment {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+// 枠画像の四隅はそのままの大きさで描き、辺と中央だけを伸ばすか敷き詰める。
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct NineSlice {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+    pub edge: SliceMode,
+    pub center: SliceMode,
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq)]
+// 吹き出しはtargetの位置をcameraで映した先に付いていき、尻尾をそちらに向ける。
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct SpeechBubble {
+    pub target: Entity,
+    pub camera: Option<Entity>,
+    pub offset: Vec3,
+    pub gap: f32,
+    pub margin: f32,
+    pub tail_path: String,
+    pub tail_width: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Reflect)]
+pub enum SliceMode {
+    #[default]
+    Stretch,
+    Tile,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
 pub enum WaitBrakerStyle {
     Auto {
         wait_sec: f32,
@@ -109,47 +476,131 @@ pub enum WaitBrakerStyle {
     },
 }
 
-#[derive(Event)]
+// theme_pathのテーマを下敷きにし、overridesに書いた項目だけがテーマより優先される。
+#[derive(Event, Debug, Clone)]
 pub struct OpenWindowEvent {
     pub window_name: String,
+    pub theme_path: Option<String>,
+    pub overrides: theme::WindowTheme,
     pub font_paths: Vec<String>,
     pub font_size: f32,
     pub font_color: Color,
     pub background_path: String,
+    pub nine_slice: Option<NineSlice>,
+    pub background_padding: Vec2,
+    pub bubble: Option<SpeechBubble>,
     pub message_window_entity: Option<Entity>,
     pub position: Vec2,
     pub box_name: String,
     pub popup: PopupType,
+    pub sinkdown: SinkDownType,
     pub typing_timing: TypingTiming,
+    pub punctuation_pauses: HashMap<char, f32>,
     pub writing: WritingStyle,
     pub feeding: FeedingStyle,
+    pub overflow: OverflowPolicy,
     pub wait_breaker: WaitBrakerStyle,
+    pub voice: Option<TypingVoice>,
+    pub outline: Option<TextOutline>,
+    pub shadow: Option<TextShadow>,
     pub script_path: String,
+    pub restore: Option<save::DialogueSave>,
     pub main_box_origin: Vec2,
     pub main_box_size: Vec2,
     pub main_alignment: TextAlignment,
+    pub main_justify: bool,
+    pub main_vertical_alignment: VerticalAlignment,
+}
+
+// 窓の開閉や進み具合をゲーム側に知らせる。
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub enum WindowLifecycleEvent {
+    Opened { window: Entity },
+    PageShown { window: Entity },
+    WaitingInput { window: Entity },
+    ScriptFinished { window: Entity },
+    Closed { window: Entity },
+}
+
+// 窓に設定された閉じ方でゲーム側から窓を閉じる。
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct CloseWindowEvent {
+    pub window: Entity,
+}
+
+impl OpenWindowEvent {
+    // テキストボックスの矩形に余白を足した、窓から見た枠の範囲。
+    pub fn frame_rect(&self) -> Rect {
+        let origin = self.main_box_origin;
+        let size = self.main_box_size;
+        let padding = self.background_padding;
+        Rect::new(
+            origin.x - padding.x,
+            origin.y - size.y - padding.y,
+            origin.x + size.x + padding.x,
+            origin.y + padding.y,
+        )
+    }
+
+    pub fn default_style(&self, asset_server: &AssetServer) -> TextStyleSnapshot {
+        TextStyleSnapshot {
+            fonts: self
+                .font_paths
+                .iter()
+                .map(|s| asset_server.load(s))
+                .collect(),
+            text_style: TextStyle {
+                font_size: self.font_size,
+                color: self.font_color,
+                ..default()
+            },
+            writing: self.writing,
+            effects: vec![],
+            voice: self.voice.clone(),
+        }
+    }
 }
 
 impl Default for OpenWindowEvent {
     fn default() -> Self {
         OpenWindowEvent {
             window_name: "Main Window".to_string(),
+            theme_path: None,
+            overrides: theme::WindowTheme::default(),
             font_paths: vec!["fonts/NotoSans-Black.ttf".to_string()],
             font_size: 27.0,
             font_color: Color::ANTIQUE_WHITE,
             background_path: "texture/ui/text_box.png".to_string(),
+            nine_slice: None,
+            background_padding: Vec2::new(20., 20.),
+            bubble: None,
             message_window_entity: None,
             position: Vec2::new(0., 0.),
             box_name: "Main Box".to_string(),
-            popup: PopupType::Scale { sec: 0.8 },
+            popup: PopupType::Scale {
+                sec: 0.8,
+                easing: Easing::Linear,
+            },
+            sinkdown: SinkDownType::Scale {
+                sec: 0.8,
+                easing: Easing::Linear,
+            },
             typing_timing: TypingTiming::ByChar { sec: 0.07 },
+            punctuation_pauses: HashMap::new(),
             writing: WritingStyle::Wipe { sec: 0.07 },
             feeding: FeedingStyle::Scroll { size: 0, sec: 40. },
+            overflow: OverflowPolicy::Paginate,
             wait_breaker: WaitBrakerStyle::Auto { wait_sec: 1.5 },
+            voice: None,
+            outline: None,
+            shadow: None,
             script_path: "scripts/message.bms".to_string(),
+            restore: None,
             main_box_origin: Vec2::new(-600., 80.),
             main_box_size: Vec2::new(1060., 260.),
             main_alignment: TextAlignment::Left,
+            main_justify: false,
+            main_vertical_alignment: VerticalAlignment::Top,
         }
     }
 }
@@ -0,0 +1,106 @@
+use super::*;
+
+#[derive(Component, Debug)]
+pub struct NineSliceBackground {
+    pub slice: NineSlice,
+    pub texture: Handle<Image>,
+    pub rect: Rect,
+    pub is_built: bool,
+}
+
+#[derive(Component, Debug)]
+pub struct NineSlicePiece;
+
+pub fn build_nine_slices(
+    mut commands: Commands,
+    mut bg_query: Query<(Entity, &mut NineSliceBackground, &RenderLayers)>,
+    images: Res<Assets<Image>>,
+) {
+    for (entity, mut bg, layer) in &mut bg_query {
+        if bg.is_built {
+            continue;
+        }
+        let Some(image) = images.get(&bg.texture) else {
+            continue;
+        };
+        let pieces = slice_pieces(&bg.slice, image.size(), bg.rect);
+        commands.entity(entity).with_children(|parent| {
+            for (src, dst) in pieces {
+                parent.spawn((
+                    SpriteBundle {
+                        texture: bg.texture.clone(),
+                        sprite: Sprite {
+                            rect: Some(src),
+                            custom_size: Some(dst.size()),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(dst.center().extend(-0.5)),
+                        ..default()
+                    },
+                    NineSlicePiece,
+                    *layer,
+                ));
+            }
+        });
+        bg.is_built = true;
+    }
+}
+
+// 画像上の切り出し範囲(y下向き)と窓上の描画範囲(y上向き)の組を返す。
+fn slice_pieces(slice: &NineSlice, image_size: Vec2, rect: Rect) -> Vec<(Rect, Rect)> {
+    let src_xs = [0., slice.left, image_size.x - slice.right, image_size.x];
+    let src_ys = [0., slice.top, image_size.y - slice.bottom, image_size.y];
+    let dst_xs = [
+        rect.min.x,
+        rect.min.x + slice.left,
+        rect.max.x - slice.right,
+        rect.max.x,
+    ];
+    let dst_ys = [
+        rect.max.y,
+        rect.max.y - slice.top,
+        rect.min.y + slice.bottom,
+        rect.min.y,
+    ];
+    let mut pieces = vec![];
+    for row in 0..3 {
+        for col in 0..3 {
+            let src = Rect::new(src_xs[col], src_ys[row], src_xs[col + 1], src_ys[row + 1]);
+            let dst = Rect::new(dst_xs[col], dst_ys[row + 1], dst_xs[col + 1], dst_ys[row]);
+            if src.is_empty() || dst.is_empty() {
+                continue;
+            }
+            let mode = match (row, col) {
+                (1, 1) => slice.center,
+                (1, _) | (_, 1) => slice.edge,
+                _ => SliceMode::Stretch,
+            };
+            match mode {
+                SliceMode::Stretch => pieces.push((src, dst)),
+                SliceMode::Tile => pieces.extend(tile_piece(src, dst)),
+            }
+        }
+    }
+    pieces
+}
+
+// 左上から敷き詰め、はみ出す端の一枚は画像側を切り詰める。
+fn tile_piece(src: Rect, dst: Rect) -> Vec<(Rect, Rect)> {
+    let tile = src.size();
+    let mut tiles = vec![];
+    let mut y = dst.max.y;
+    while y > dst.min.y {
+        let h = tile.y.min(y - dst.min.y);
+        let mut x = dst.min.x;
+        while x < dst.max.x {
+            let w = tile.x.min(dst.max.x - x);
+            tiles.push((
+                Rect::new(src.min.x, src.min.y, src.min.x + w, src.min.y + h),
+                Rect::new(x, y - h, x + w, y),
+            ));
+            x += w;
+        }
+        y -= h;
+    }
+    tiles
+}
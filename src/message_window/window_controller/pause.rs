@@ -0,0 +1,63 @@
+use super::*;
+
+// 実際に止まっている窓とその子孫に付く印。時間で進むシステムはこれを避ける。
+#[derive(Component, Debug)]
+pub struct Paused;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct WindowPause {
+    pub is_paused: bool,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct MessagePause {
+    pub is_paused: bool,
+}
+
+// windowがNoneなら全ての窓をまとめて止める。
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub enum PauseEvent {
+    Pause { window: Option<Entity> },
+    Resume { window: Option<Entity> },
+}
+
+pub fn pause_or_resume(
+    mut events: EventReader<PauseEvent>,
+    mut window_query: Query<&mut WindowPause>,
+    mut global: ResMut<MessagePause>,
+) {
+    for event in events.iter() {
+        let (window_opt, is_paused) = match *event {
+            PauseEvent::Pause { window: w } => (w, true),
+            PauseEvent::Resume { window: w } => (w, false),
+        };
+        match window_opt {
+            Some(w) => {
+                if let Ok(mut wp) = window_query.get_mut(w) {
+                    wp.is_paused = is_paused;
+                }
+            }
+            None => global.is_paused = is_paused,
+        }
+    }
+}
+
+pub fn propagate_pause(
+    mut commands: Commands,
+    window_query: Query<(Entity, &WindowPause)>,
+    children_query: Query<&Children>,
+    paused_query: Query<(), With<Paused>>,
+    global: Res<MessagePause>,
+) {
+    for (mw_entity, wp) in &window_query {
+        let is_paused = global.is_paused || wp.is_paused;
+        for entity in std::iter::once(mw_entity).chain(children_query.iter_descendants(mw_entity)) {
+            let has_mark = paused_query.contains(entity);
+            if is_paused && !has_mark {
+                commands.entity(entity).insert(Paused);
+            } else if !is_paused && has_mark {
+                commands.entity(entity).remove::<Paused>();
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use super::super::*;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayMode {
+    pub auto: bool,
+    pub skip: bool,
+}
+
+#[derive(Resource, Debug)]
+pub struct PlayModeConfig {
+    pub auto_base_sec: f32,
+    pub auto_sec_per_char: f32,
+    pub auto_keys: Vec<KeyCode>,
+    pub skip_keys: Vec<KeyCode>,
+    pub hold_skip_keys: Vec<KeyCode>,
+}
+
+impl Default for PlayModeConfig {
+    fn default() -> Self {
+        PlayModeConfig {
+            auto_base_sec: 1.0,
+            auto_sec_per_char: 0.05,
+            auto_keys: vec![KeyCode::A],
+            skip_keys: vec![KeyCode::S],
+            hold_skip_keys: vec![KeyCode::ControlLeft, KeyCode::ControlRight],
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct AutoAdvance {
+    pub timer: Timer,
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct SetAutoMode {
+    pub is_on: bool,
+}
+
+// 選択肢が出たときはstop_skip_on_choiceで止まる。それ以外で止めたいときは、スクリプトからis_on: falseを送る。
+#[derive(Reflect, Default, Debug)]
+pub struct SetSkipMode {
+    pub is_on: bool,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn change_play_mode(
+    mut window_query: Query<(Entity, &mut PlayMode), (With<MessageWindow>, Without<Paused>)>,
+    mut bms_reader: EventReader<BMSEvent>,
+    keys: Res<Input<KeyCode>>,
+    config: Res<PlayModeConfig>,
+) {
+    let toggle_auto = keys.any_just_pressed(config.auto_keys.iter().copied());
+    let toggle_skip = keys.any_just_pressed(config.skip_keys.iter().copied());
+    for (_, mut mode) in &mut window_query {
+        if toggle_auto {
+            mode.auto = !mode.auto;
+        }
+        if toggle_skip {
+            mode.skip = !mode.skip;
+        }
+    }
+    for event_wrapper in bms_reader.iter() {
+        let auto_opt = event_wrapper.get_opt::<SetAutoMode>().map(|m| m.is_on);
+        let skip_opt = event_wrapper.get_opt::<SetSkipMode>().map(|m| m.is_on);
+        for (mw_entity, mut mode) in &mut window_query {
+            if !event_wrapper.is_for(mw_entity) {
+                continue;
+            }
+            if let Some(is_on) = auto_opt {
+                mode.auto = is_on;
+            }
+            if let Some(is_on) = skip_opt {
+                mode.skip = is_on;
+            }
+        }
+    }
+}
+
+// テキストボックス以外の入力待ちは選択肢として扱い、出てきたらスキップを切る。
+#[allow(clippy::type_complexity)]
+pub fn stop_skip_on_choice(
+    mut window_query: Query<&mut PlayMode, With<MessageWindow>>,
+    choice_query: Query<(), (Or<(Added<WaitInputGo>, Added<Selected>)>, Without<TextBox>)>,
+) {
+    if choice_query.is_empty() {
+        return;
+    }
+    for mut mode in &mut window_query {
+        if mode.skip {
+            mode.skip = false;
+        }
+    }
+}
+
+// 入力待ちの窓を、ページの文字数に合わせた時間が経ったら代わりに進める。
+#[allow(clippy::type_complexity)]
+pub fn auto_advance(
+    mut commands: Commands,
+    window_query: Query<(&PlayMode, &WindowState), With<MessageWindow>>,
+    mut text_box_query: Query<
+        (Entity, Ref<WaitInputGo>, &Parent, Option<&mut AutoAdvance>),
+        (With<TextBox>, Without<Paused>),
+    >,
+    line_text_query: Query<(Entity, &LineGlyphs), With<LineText>>,
+    parents: Query<&Parent>,
+    viewer_query: Query<(), With<BacklogViewer>>,
+    config: Res<PlayModeConfig>,
+    time: Res<Time>,
+    mut bms_event: EventWriter<BMSEvent>,
+    type_registry: Res<AppTypeRegistry>,
+) {
+    if !viewer_query.is_empty() {
+        return;
+    }
+    for (tb_entity, wig, tb_parent, auto_opt) in &mut text_box_query {
+        let w_entity = tb_parent.get();
+        let is_auto = window_query
+            .get(w_entity)
+            .is_ok_and(|(mode, ws)| mode.auto && !mode.skip && *ws == WindowState::Waiting);
+        if !is_auto {
+            if auto_opt.is_some() {
+                commands.entity(tb_entity).remove::<AutoAdvance>();
+            }
+            continue;
+        }
+        match auto_opt {
+            Some(mut auto) if !wig.is_changed() => {
+                if auto.timer.tick(time.delta()).finished() {
+                    if let Ok(ref_value) = read_ron(&type_registry, wig.ron.clone()) {
+                        bms_event.send(BMSEvent {
+                            value: ref_value,
+                            window: Some(w_entity),
+                        });
+                    }
+                    commands
+                        .entity(tb_entity)
+                        .remove::<(WaitInputGo, AutoAdvance)>();
+                }
+            }
+            _ => {
+                let (count, remaining) = line_text_query
+                    .iter()
+                    .filter(|(lt_entity, _)| {
+                        parents.iter_ancestors(*lt_entity).any(|e| e == tb_entity)
+                    })
+                    .fold((0, 0f32), |(c, r), (_, lg)| {
+                        (c + lg.glyphs.len(), r.max(lg.remaining_secs()))
+                    });
+                let sec =
+                    remaining + config.auto_base_sec + config.auto_sec_per_char * count as f32;
+                commands.entity(tb_entity).insert(AutoAdvance {
+                    timer: Timer::from_seconds(sec, TimerMode::Once),
+                });
+            }
+        }
+    }
+}
+
+// スキップ中は文字送りも待ちも送りも、押されたものとしてすぐに進める。
+#[allow(clippy::type_complexity)]
+pub fn skip_forward(
+    mut commands: Commands,
+    window_query: Query<&PlayMode, With<MessageWindow>>,
+    text_box_query: Query<(Entity, &WaitInputGo, &Parent), (With<TextBox>, Without<Paused>)>,
+    mut feed_trigger_query: Query<(&mut WaitFeedingTrigger, &Parent), Without<Paused>>,
+    mut sink_trigger_query: Query<(Entity, &mut WaitSinkingTrigger), Without<Paused>>,
+    viewer_query: Query<(), With<BacklogViewer>>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    config: Res<PlayModeConfig>,
+    mut bms_event: EventWriter<BMSEvent>,
+    type_registry: Res<AppTypeRegistry>,
+) {
+    if !viewer_query.is_empty() {
+        return;
+    }
+    let gamepad_skip_button = gamepads.iter().next().map(|x| GamepadButton {
+        gamepad: x,
+        button_type: GamepadButtonType::RightTrigger,
+    });
+    let is_held = keys.any_pressed(config.hold_skip_keys.iter().copied())
+        || gamepad_skip_button.is_some_and(|x| gamepad_buttons.pressed(x));
+    let is_skipping =
+        |w_entity: Entity| is_held || window_query.get(w_entity).is_ok_and(|mode| mode.skip);
+    for (tb_entity, wig, tb_parent) in &text_box_query {
+        if !is_skipping(tb_parent.get()) {
+            continue;
+        }
+        if let Ok(ref_value) = read_ron(&type_registry, wig.ron.clone()) {
+            bms_event.send(BMSEvent {
+                value: ref_value,
+                window: Some(tb_parent.get()),
+            });
+        }
+        commands.entity(tb_entity).remove::<WaitInputGo>();
+    }
+    for (mut wft, tb_parent) in &mut feed_trigger_query {
+        if is_skipping(tb_parent.get()) {
+            finish_timer(&mut wft.timer);
+        }
+    }
+    for (w_entity, mut wst) in &mut sink_trigger_query {
+        if is_skipping(w_entity) {
+            finish_timer(&mut wst.timer);
+        }
+    }
+}
+
+fn finish_timer(timer: &mut Timer) {
+    let duration = timer.duration();
+    timer.set_elapsed(duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_skipping_window(app: &mut App) -> Entity {
+        app.world
+            .spawn((
+                MessageWindow {
+                    name: "test".to_string(),
+                },
+                PlayMode {
+                    auto: false,
+                    skip: true,
+                },
+            ))
+            .id()
+    }
+
+    fn wig() -> WaitInputGo {
+        WaitInputGo {
+            ron: String::new(),
+            area: Rect::default(),
+        }
+    }
+
+    #[test]
+    fn test_stop_skip_on_choice() {
+        let mut app = App::new();
+        app.add_systems(Update, stop_skip_on_choice);
+        let window = spawn_skipping_window(&mut app);
+        app.world.spawn((
+            TextBox {
+                name: "test".to_string(),
+            },
+            wig(),
+        ));
+        app.update();
+        assert!(app.world.get::<PlayMode>(window).unwrap().skip);
+        app.world.spawn((wig(), Selected));
+        app.update();
+        assert!(!app.world.get::<PlayMode>(window).unwrap().skip);
+    }
+}
@@ -1,83 +1,223 @@
+use super::bubble::{BubbleFrame, BubbleTail};
+use super::nine_slice::NineSliceBackground;
+use super::pause::{Paused, WindowPause};
+use super::play_mode::PlayMode;
+use super::save::RestoredWait;
+use super::theme::{WindowTheme, WindowThemeRef};
 use super::*;
+use bevy::asset::LoadState;
 use bevy::render::view::Visibility::Visible;
+use bevy::window::PrimaryWindow;
 
+// テーマを指定された窓は、テーマが読み込めてから重ねた設定で開く。
 pub fn open_window(
     mut commands: Commands,
-    mut mw_query: Query<Entity, With<Current>>,
     mut ow_event: EventReader<OpenWindowEvent>,
+    mut waiting_themes: Local<Vec<(OpenWindowEvent, Handle<WindowTheme>)>>,
+    themes: Res<Assets<WindowTheme>>,
     asset_server: Res<AssetServer>,
     setup_config: Res<SetupConfig>,
 ) {
     for window_config in &mut ow_event {
-        let mwb = MessageWindowBundle {
-            message_window: MessageWindow {
-                name: window_config.window_name.clone(),
-            },
-            state: WindowState::Preparing,
-            waitting: window_config.wait_breaker,
-            script: LoadedScript {
-                bms_handle: asset_server.load(window_config.script_path.clone()),
-                order_list: None,
+        match &window_config.theme_path {
+            Some(path) => {
+                waiting_themes.push((window_config.clone(), asset_server.load(path.as_str())))
+            }
+            None => spawn_window(
+                &mut commands,
+                &window_config.with_theme(&WindowTheme::default()),
+                None,
+                &asset_server,
+                &setup_config,
+            ),
+        }
+    }
+    let mut still_waiting = vec![];
+    for (window_config, handle) in waiting_themes.drain(..) {
+        if let Some(theme) = themes.get(&handle) {
+            let themed = window_config.with_theme(theme);
+            spawn_window(
+                &mut commands,
+                &themed,
+                Some((handle, window_config)),
+                &asset_server,
+                &setup_config,
+            );
+        } else if asset_server.get_load_state(&handle) == LoadState::Failed {
+            warn!("failed to load window theme {:?}", window_config.theme_path);
+            spawn_window(
+                &mut commands,
+                &window_config.with_theme(&WindowTheme::default()),
+                None,
+                &asset_server,
+                &setup_config,
+            );
+        } else {
+            still_waiting.push((window_config, handle));
+        }
+    }
+    *waiting_themes = still_waiting;
+}
+
+fn spawn_window(
+    commands: &mut Commands,
+    window_config: &OpenWindowEvent,
+    theme: Option<(Handle<WindowTheme>, OpenWindowEvent)>,
+    asset_server: &AssetServer,
+    setup_config: &SetupConfig,
+) {
+    // セーブから戻すときは、そのページの頭からの命令と文字の設定で開き直す。
+    let restore = window_config.restore.as_ref();
+    let mwb = MessageWindowBundle {
+        message_window: MessageWindow {
+            name: window_config.window_name.clone(),
+        },
+        speaker: Speaker {
+            name: restore.map(|r| r.speaker.clone()).unwrap_or_default(),
+        },
+        variables: ScriptVariables {
+            values: restore.map(|r| r.variables.clone()).unwrap_or_default(),
+        },
+        state: WindowState::Preparing,
+        waitting: window_config.wait_breaker,
+        script: LoadedScript {
+            bms_handle: asset_server
+                .load(restore.map_or(window_config.script_path.clone(), |r| r.script_path.clone())),
+            order_list: restore.map(|r| r.orders.iter().rev().cloned().collect()),
+            section: restore.map(|r| r.section.clone()).unwrap_or_default(),
+        },
+        popup_type: window_config.popup,
+        sinkdown_type: window_config.sinkdown,
+        play_mode: PlayMode::default(),
+        pause: WindowPause::default(),
+    };
+    let background = asset_server.load(window_config.background_path.clone());
+    let nine_slice = window_config.nine_slice.map(|slice| NineSliceBackground {
+        slice,
+        texture: background.clone(),
+        rect: window_config.frame_rect(),
+        is_built: false,
+    });
+    let mw_spirte = SpriteBundle {
+        texture: if nine_slice.is_some() {
+            default()
+        } else {
+            background
+        },
+        sprite: Sprite {
+            color: if nine_slice.is_some() {
+                Color::WHITE.with_a(0.)
+            } else {
+                Color::WHITE
             },
-            popup_type: window_config.popup,
-        };
-        let mw_spirte = SpriteBundle {
-            texture: asset_server.load(window_config.background_path.clone()),
-            transform: Transform::from_translation(window_config.position.extend(0.0)),
             ..default()
-        };
-        let tbb = TextBoxBundle {
-            text_box: TextBox {
-                name: window_config.box_name.clone(),
-            },
-            feeding: window_config.feeding,
-            config: TypeTextConfig {
-                fonts: window_config
-                    .font_paths
-                    .iter()
-                    .map(|s| asset_server.load(s))
-                    .collect(),
-                text_style: TextStyle {
-                    font_size: window_config.font_size,
-                    color: window_config.font_color,
+        },
+        transform: Transform::from_translation(window_config.position.extend(0.0)),
+        ..default()
+    };
+    let default_style = window_config.default_style(asset_server);
+    let mut tbb = TextBoxBundle {
+        text_box: TextBox {
+            name: window_config.box_name.clone(),
+        },
+        feeding: window_config.feeding,
+        overflow: window_config.overflow,
+        vertical_alignment: window_config.main_vertical_alignment,
+        config: TypeTextConfig {
+            fonts: default_style.fonts.clone(),
+            text_style: default_style.text_style.clone(),
+            writing: default_style.writing,
+            effects: default_style.effects.clone(),
+            voice: default_style.voice.clone(),
+            typing_timing: window_config.typing_timing,
+            punctuation_pauses: window_config.punctuation_pauses.clone(),
+            outline: window_config.outline,
+            shadow: window_config.shadow,
+            layer: RenderLayers::layer(setup_config.render_layer),
+            alignment: window_config.main_alignment,
+            justify: window_config.main_justify,
+            style_stack: vec![],
+            default_style,
+        },
+        blip_counter: BlipCounter::default(),
+        cursor: ScriptCursor::default(),
+    };
+    if let Some(save) = restore {
+        tbb.config.restore(save.style.to_snapshot(asset_server));
+        tbb.config.style_stack = save
+            .style_stack
+            .iter()
+            .map(|s| s.to_snapshot(asset_server))
+            .collect();
+    }
+    let tb_sprite = SpriteBundle {
+        sprite: Sprite {
+            anchor: Anchor::TopLeft,
+            color: Color::WHITE.with_a(0.),
+            custom_size: Some(window_config.main_box_size),
+            ..default()
+        },
+        transform: Transform::from_translation(window_config.main_box_origin.extend(0.0)),
+        ..default()
+    };
+    let layer = RenderLayers::layer(setup_config.render_layer);
+    let mw = match window_config.message_window_entity {
+        Some(entity) => entity,
+        None => commands.spawn(mw_spirte).id(),
+    };
+    let additional_mw = (Hidden,);
+    commands
+        .entity(mw)
+        .insert((mwb, layer, Current, additional_mw));
+    if let Some(save) = restore {
+        commands.entity(mw).insert(RestoredWait::from_save(save));
+    }
+    // 後からテーマを切り替えたときは、テーマを重ねる前の設定に重ね直す。
+    let (handle, base) = match theme {
+        Some((handle, base)) => (Some(handle), base),
+        None => (None, window_config.clone()),
+    };
+    commands.entity(mw).insert(WindowThemeRef {
+        handle,
+        base: OpenWindowEvent {
+            restore: None,
+            ..base
+        },
+        is_applied: true,
+    });
+    if let Some(bg) = nine_slice {
+        commands.entity(mw).insert(bg);
+    }
+    if let Some(bubble) = window_config.bubble.clone() {
+        let tail = commands
+            .spawn((
+                SpriteBundle {
+                    texture: asset_server.load(bubble.tail_path.clone()),
+                    transform: Transform::from_xyz(0., 0., -0.5),
                     ..default()
                 },
-                writing: window_config.writing,
-                typing_timing: window_config.typing_timing,
-                layer: RenderLayers::layer(setup_config.render_layer),
-                alignment: window_config.main_alignment,
-            },
-        };
-        let tb_sprite = SpriteBundle {
-            sprite: Sprite {
-                anchor: Anchor::TopLeft,
-                color: Color::WHITE.with_a(0.),
-                custom_size: Some(window_config.main_box_size),
-                ..default()
+                BubbleTail,
+                layer,
+            ))
+            .id();
+        commands.entity(mw).add_child(tail).insert((
+            bubble,
+            BubbleFrame {
+                rect: window_config.frame_rect(),
+                tail,
             },
-            transform: Transform::from_translation(window_config.main_box_origin.extend(0.0)),
-            ..default()
-        };
-        for entity in &mut mw_query {
-            commands.entity(entity).remove::<Current>();
-        }
-        let layer = RenderLayers::layer(setup_config.render_layer);
-        let mw = match window_config.message_window_entity {
-            Some(entity) => entity,
-            None => commands.spawn(mw_spirte).id(),
-        };
-        let additional_mw = (Hidden,);
-        commands
-            .entity(mw)
-            .insert((mwb, layer, Current, additional_mw));
-        let tb = commands.spawn((tbb, tb_sprite, layer, Current)).id();
-        commands.entity(mw).add_child(tb);
+        ));
     }
+    let tb = commands.spawn((tbb, tb_sprite, layer, Current)).id();
+    commands.entity(mw).add_child(tb);
 }
 
 #[derive(Component, Debug)]
-pub struct ScalingUp {
-    pub add_per_sec: f32,
+pub struct PoppingUp {
+    pub popup: PopupType,
+    pub elapsed: f32,
+    pub origin: Vec3,
+    pub base_alphas: HashMap<Entity, f32>,
 }
 
 #[allow(clippy::type_complexity)]
@@ -90,39 +230,132 @@ pub fn window_popper(
             &PopupType,
             &mut Visibility,
             &mut Transform,
+            Option<&mut Sprite>,
         ),
-        (With<Current>, With<MessageWindow>),
+        (With<Current>, With<MessageWindow>, Without<Paused>),
     >,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
 ) {
-    for (ent, mut ws, pt, mut vis, mut tf) in &mut mw_query {
+    let screen = screen_size(&window_query);
+    for (ent, mut ws, pt, mut vis, mut tf, sprite_opt) in &mut mw_query {
         if *ws == WindowState::Preparing {
-            match pt {
-                PopupType::Scale { sec: s } => {
-                    tf.scale = Vec3::new(0., 0., 0.);
-                    commands.entity(ent).insert(ScalingUp {
-                        add_per_sec: 1.0 / s,
-                    });
-                }
-            }
             *vis = Visible;
+            if *pt == PopupType::Instant {
+                *ws = WindowState::Typing;
+                lifecycle_event.send(WindowLifecycleEvent::Opened { window: ent });
+                continue;
+            }
+            let mut popping = PoppingUp {
+                popup: *pt,
+                elapsed: 0.,
+                origin: tf.translation,
+                base_alphas: HashMap::new(),
+            };
+            let alpha = pose_window(pt, 0., popping.origin, screen, &mut tf);
+            if let Some(mut sprite) = sprite_opt.filter(|_| is_fading(pt)) {
+                let a = sprite.color.a();
+                popping.base_alphas.insert(ent, a);
+                sprite.color.set_a(a * alpha);
+            }
+            commands.entity(ent).insert(popping);
             *ws = WindowState::PoppingUp;
         }
     }
 }
 
-pub fn scaling_up(
+pub fn popping_up(
     mut commands: Commands,
-    mut mw_query: Query<(Entity, &mut Transform, &ScalingUp, &mut WindowState)>,
+    mut mw_query: Query<
+        (Entity, &mut Transform, &mut PoppingUp, &mut WindowState),
+        Without<Paused>,
+    >,
+    children_query: Query<&Children>,
+    mut sprite_query: Query<&mut Sprite>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
     time: Res<Time>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
 ) {
-    for (ent, mut tf, ScalingUp { add_per_sec: aps }, mut ws) in &mut mw_query {
-        if tf.scale.x >= 1.0 {
-            tf.scale = Vec3::new(1., 1., 1.);
+    let screen = screen_size(&window_query);
+    for (ent, mut tf, mut popping, mut ws) in &mut mw_query {
+        popping.elapsed += time.delta_seconds();
+        let sec = popup_sec(&popping.popup);
+        let t = if sec > 0. { popping.elapsed / sec } else { 1. };
+        let alpha = pose_window(&popping.popup, t, popping.origin, screen, &mut tf);
+        if is_fading(&popping.popup) {
+            for entity in std::iter::once(ent).chain(children_query.iter_descendants(ent)) {
+                if let Ok(mut sprite) = sprite_query.get_mut(entity) {
+                    let base = *popping
+                        .base_alphas
+                        .entry(entity)
+                        .or_insert(sprite.color.a());
+                    sprite.color.set_a(base * alpha);
+                }
+            }
+        }
+        if t >= 1. {
             *ws = WindowState::Typing;
-            commands.entity(ent).remove::<ScalingUp>();
-        } else {
-            tf.scale.x += time.delta_seconds() * aps;
-            tf.scale.y += time.delta_seconds() * aps;
-        };
+            commands.entity(ent).remove::<PoppingUp>();
+            lifecycle_event.send(WindowLifecycleEvent::Opened { window: ent });
+        }
+    }
+}
+
+fn popup_sec(popup: &PopupType) -> f32 {
+    match *popup {
+        PopupType::Scale { sec: s, .. }
+        | PopupType::Fade { sec: s, .. }
+        | PopupType::ScaleFade { sec: s, .. }
+        | PopupType::Slide { sec: s, .. } => s,
+        PopupType::Instant => 0.,
+    }
+}
+
+fn is_fading(popup: &PopupType) -> bool {
+    matches!(popup, PopupType::Fade { .. } | PopupType::ScaleFade { .. })
+}
+
+pub fn screen_size(window_query: &Query<&Window, With<PrimaryWindow>>) -> Vec2 {
+    window_query
+        .get_single()
+        .map(|w| Vec2::new(w.width(), w.height()))
+        .unwrap_or_default()
+}
+
+// 進み具合tに合わせて窓の大きさと位置を決め、透明度の割合を返す。
+pub fn pose_window(
+    popup: &PopupType,
+    t: f32,
+    origin: Vec3,
+    screen: Vec2,
+    tf: &mut Transform,
+) -> f32 {
+    match *popup {
+        PopupType::Scale { easing: e, .. } => {
+            let scale = e.apply(t);
+            tf.scale = Vec3::new(scale, scale, 1.);
+            1.
+        }
+        PopupType::Fade { easing: e, .. } => e.apply(t).clamp(0., 1.),
+        PopupType::ScaleFade { easing: e, .. } => {
+            let scale = e.apply(t);
+            tf.scale = Vec3::new(scale, scale, 1.);
+            scale.clamp(0., 1.)
+        }
+        PopupType::Slide {
+            from: edge,
+            easing: e,
+            ..
+        } => {
+            let offset = match edge {
+                ScreenEdge::Bottom => Vec2::new(0., -screen.y),
+                ScreenEdge::Top => Vec2::new(0., screen.y),
+                ScreenEdge::Left => Vec2::new(-screen.x, 0.),
+                ScreenEdge::Right => Vec2::new(screen.x, 0.),
+            };
+            tf.translation = origin + (offset * (1. - e.apply(t))).extend(0.);
+            1.
+        }
+        PopupType::Instant => 1.,
     }
 }
@@ -0,0 +1,419 @@
+use super::sinkdown::SinkingDown;
+use crate::message_window::*;
+use bevy::ecs::system::SystemParam;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Reflect, Default, Debug, Clone, PartialEq)]
+pub struct SavedTextStyle {
+    pub font_paths: Vec<String>,
+    pub font_size: f32,
+    pub color: Color,
+    pub writing: WritingStyle,
+    pub effects: Vec<TextEffect>,
+    pub voice: Option<TypingVoice>,
+}
+
+impl SavedTextStyle {
+    pub fn from_snapshot(snapshot: &TextStyleSnapshot, asset_server: &AssetServer) -> Self {
+        SavedTextStyle {
+            font_paths: snapshot
+                .fonts
+                .iter()
+                .filter_map(|h| asset_server.get_handle_path(h))
+                .map(|p| p.path().to_string_lossy().to_string())
+                .collect(),
+            font_size: snapshot.text_style.font_size,
+            color: snapshot.text_style.color,
+            writing: snapshot.writing,
+            effects: snapshot.effects.clone(),
+            voice: snapshot.voice.clone(),
+        }
+    }
+
+    pub fn to_snapshot(&self, asset_server: &AssetServer) -> TextStyleSnapshot {
+        TextStyleSnapshot {
+            fonts: self
+                .font_paths
+                .iter()
+                .map(|p| asset_server.load(p))
+                .collect(),
+            text_style: TextStyle {
+                font_size: self.font_size,
+                color: self.color,
+                ..default()
+            },
+            writing: self.writing,
+            effects: self.effects.clone(),
+            voice: self.voice.clone(),
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug, Clone, PartialEq)]
+pub enum SavedWait {
+    #[default]
+    None,
+    Input,
+    Feeding {
+        remaining_sec: f32,
+    },
+    Sinking {
+        sink_type: SinkDownType,
+        remaining_sec: f32,
+    },
+}
+
+// 戻すときは今のページの頭から読み直すので、ページ内の待ちや送りはそこで作り直される。
+// stateとwaitは、読み直したページが待ちに着いたところでRestoredWaitとして戻す。
+#[derive(Reflect, Default, Debug, Clone, PartialEq)]
+pub struct DialogueSave {
+    pub window_name: String,
+    pub script_path: String,
+    pub section: String,
+    pub orders: Vec<Order>,
+    pub style: SavedTextStyle,
+    pub style_stack: Vec<SavedTextStyle>,
+    pub speaker: String,
+    pub variables: HashMap<String, String>,
+    pub state: WindowState,
+    pub wait: SavedWait,
+}
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct RestoredWait {
+    pub state: WindowState,
+    pub wait: SavedWait,
+}
+
+impl RestoredWait {
+    pub fn from_save(save: &DialogueSave) -> Self {
+        RestoredWait {
+            state: save.state,
+            wait: save.wait.clone(),
+        }
+    }
+
+    // 送りや沈み込みが始まっていたなら、その待ちはもう過ぎている。
+    fn is_passed(&self) -> bool {
+        matches!(
+            self.state,
+            WindowState::Feeding | WindowState::SinkingDown | WindowState::Fixed
+        )
+    }
+}
+
+#[derive(SystemParam)]
+#[allow(clippy::type_complexity)]
+pub struct DialogueSaver<'w, 's> {
+    window: Query<
+        'w,
+        's,
+        (
+            &'static MessageWindow,
+            &'static LoadedScript,
+            &'static WindowState,
+            &'static Speaker,
+            &'static ScriptVariables,
+            Option<&'static WaitSinkingTrigger>,
+            Option<&'static SinkingDown>,
+        ),
+    >,
+    text_box: Query<
+        'w,
+        's,
+        (
+            &'static ScriptCursor,
+            &'static TypeTextConfig,
+            &'static Parent,
+            Option<&'static WaitFeedingTrigger>,
+            Option<&'static WaitInputGo>,
+        ),
+        With<TextBox>,
+    >,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl DialogueSaver<'_, '_> {
+    pub fn save(&self, window: Entity) -> Option<DialogueSave> {
+        let (mw, script, ws, speaker, variables, sink_opt, sinking_opt) =
+            self.window.get(window).ok()?;
+        let (cursor, config, _, feed_opt, wig_opt) = self
+            .text_box
+            .iter()
+            .find(|(_, _, parent, ..)| parent.get() == window)?;
+        let (style, stack) = cursor
+            .page_style
+            .clone()
+            .unwrap_or_else(|| (config.snapshot(), config.style_stack.clone()));
+        let remaining = script.order_list.iter().flatten().rev().cloned();
+        let wait = match (sink_opt, feed_opt, wig_opt) {
+            _ if sinking_opt.is_some() => SavedWait::Sinking {
+                sink_type: sinking_opt.map(|s| s.sink_type).unwrap_or_default(),
+                remaining_sec: 0.,
+            },
+            (Some(wst), ..) => SavedWait::Sinking {
+                sink_type: wst.sink_type,
+                remaining_sec: wst.timer.remaining_secs(),
+            },
+            (_, Some(wft), _) => SavedWait::Feeding {
+                remaining_sec: wft.timer.remaining_secs(),
+            },
+            (_, _, Some(_)) => SavedWait::Input,
+            _ => SavedWait::None,
+        };
+        Some(DialogueSave {
+            window_name: mw.name.clone(),
+            script_path: self
+                .asset_server
+                .get_handle_path(&script.bms_handle)
+                .map(|p| p.path().to_string_lossy().to_string())
+                .unwrap_or_default(),
+            section: script.section.clone(),
+            orders: cursor
+                .page_orders
+                .iter()
+                .cloned()
+                .chain(remaining)
+                .collect(),
+            style: SavedTextStyle::from_snapshot(&style, &self.asset_server),
+            style_stack: stack
+                .iter()
+                .map(|s| SavedTextStyle::from_snapshot(s, &self.asset_server))
+                .collect(),
+            speaker: speaker.name.clone(),
+            variables: variables.values.clone(),
+            state: *ws,
+            wait,
+        })
+    }
+}
+
+// 読み直したページが待ちに着いたら、セーブした時点の残り時間に合わせるか、過ぎていた待ちをそのまま進める。
+#[allow(clippy::type_complexity)]
+pub fn resume_restored_wait(
+    mut commands: Commands,
+    mut window_query: Query<
+        (
+            Entity,
+            &RestoredWait,
+            &WindowState,
+            Option<&mut WaitSinkingTrigger>,
+        ),
+        With<MessageWindow>,
+    >,
+    mut text_box_query: Query<
+        (
+            Entity,
+            &Parent,
+            Option<&mut WaitFeedingTrigger>,
+            Option<&WaitInputGo>,
+        ),
+        With<TextBox>,
+    >,
+    mut bms_event: EventWriter<BMSEvent>,
+    type_registry: Res<AppTypeRegistry>,
+) {
+    for (w_entity, restored, ws, sink_opt) in &mut window_query {
+        if *ws != WindowState::Waiting {
+            continue;
+        }
+        let Some((tb_entity, _, feed_opt, wig_opt)) = text_box_query
+            .iter_mut()
+            .find(|(_, parent, ..)| parent.get() == w_entity)
+        else {
+            continue;
+        };
+        if let Some(mut wst) = sink_opt {
+            match restored.wait {
+                SavedWait::Sinking {
+                    remaining_sec: r, ..
+                } => set_remaining(&mut wst.timer, r),
+                _ if restored.is_passed() => set_remaining(&mut wst.timer, 0.),
+                _ => (),
+            }
+        } else if let Some(mut wft) = feed_opt {
+            match restored.wait {
+                SavedWait::Feeding { remaining_sec: r } => set_remaining(&mut wft.timer, r),
+                _ if restored.is_passed() => set_remaining(&mut wft.timer, 0.),
+                _ => (),
+            }
+        } else if let Some(wig) = wig_opt {
+            if restored.is_passed() {
+                if let Ok(ref_value) = read_ron(&type_registry, wig.ron.clone()) {
+                    bms_event.send(BMSEvent {
+                        value: ref_value,
+                        window: Some(w_entity),
+                    });
+                }
+                commands.entity(tb_entity).remove::<WaitInputGo>();
+            }
+        } else {
+            continue;
+        }
+        commands.entity(w_entity).remove::<RestoredWait>();
+    }
+}
+
+fn set_remaining(timer: &mut Timer, sec: f32) {
+    let duration = timer.duration();
+    let remaining = Duration::from_secs_f32(sec.max(0.)).min(duration);
+    timer.set_elapsed(duration - remaining);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_event::<BMSEvent>()
+            .register_type::<DialogueSave>()
+            .register_type::<SavedTextStyle>()
+            .register_type::<Vec<SavedTextStyle>>()
+            .register_type::<SavedWait>()
+            .register_type::<WindowState>()
+            .register_type::<Order>()
+            .register_type::<Vec<Order>>()
+            .register_type::<WritingStyle>()
+            .register_type::<TextEffect>()
+            .register_type::<Vec<TextEffect>>()
+            .register_type::<TypingVoice>()
+            .register_type::<Option<TypingVoice>>()
+            .register_type::<SinkDownType>()
+            .register_type::<Easing>()
+            .register_type::<Color>()
+            .register_type::<Vec<String>>()
+            .register_type::<HashMap<String, String>>()
+            .add_systems(Update, resume_restored_wait);
+        app
+    }
+
+    fn text_box_config() -> TypeTextConfig {
+        let style = TextStyleSnapshot {
+            fonts: vec![],
+            text_style: TextStyle::default(),
+            writing: WritingStyle::Put,
+            effects: vec![],
+            voice: None,
+        };
+        TypeTextConfig {
+            fonts: vec![],
+            text_style: TextStyle::default(),
+            writing: WritingStyle::Put,
+            effects: vec![],
+            voice: None,
+            typing_timing: TypingTiming::ByChar { sec: 0.07 },
+            punctuation_pauses: HashMap::new(),
+            outline: None,
+            shadow: None,
+            layer: default(),
+            alignment: TextAlignment::Left,
+            justify: false,
+            style_stack: vec![],
+            default_style: style,
+        }
+    }
+
+    fn feed_trigger(remaining_sec: f32) -> WaitFeedingTrigger {
+        let mut timer = Timer::from_seconds(1.5, TimerMode::Once);
+        set_remaining(&mut timer, remaining_sec);
+        WaitFeedingTrigger { timer }
+    }
+
+    fn spawn_waiting_window(app: &mut App, variables: HashMap<String, String>) -> Entity {
+        let window = app
+            .world
+            .spawn((
+                MessageWindow {
+                    name: "test".to_string(),
+                },
+                LoadedScript {
+                    bms_handle: Handle::default(),
+                    order_list: Some(vec![Order::Type {
+                        character: "b".to_string(),
+                    }]),
+                    section: "キツネの森".to_string(),
+                },
+                WindowState::Waiting,
+                Speaker {
+                    name: "キツネ".to_string(),
+                },
+                ScriptVariables { values: variables },
+            ))
+            .id();
+        app.world
+            .spawn((
+                TextBox {
+                    name: "test".to_string(),
+                },
+                ScriptCursor {
+                    page_orders: vec![
+                        Order::Type {
+                            character: "a".to_string(),
+                        },
+                        Order::PageFeed,
+                    ],
+                    ..default()
+                },
+                text_box_config(),
+                feed_trigger(0.4),
+            ))
+            .set_parent(window);
+        window
+    }
+
+    #[test]
+    fn test_round_trip_waiting_window() {
+        let mut app = test_app();
+        let variables = HashMap::from([("met_fox".to_string(), "true".to_string())]);
+        let window = spawn_waiting_window(&mut app, variables.clone());
+        let mut saver = SystemState::<DialogueSaver>::new(&mut app.world);
+        let save = saver.get(&app.world).save(window).unwrap();
+        assert_eq!(save.section, "キツネの森");
+        assert_eq!(save.variables, variables);
+        assert_eq!(save.state, WindowState::Waiting);
+        assert!(
+            matches!(save.wait, SavedWait::Feeding { remaining_sec: r } if (r - 0.4).abs() < 1e-3)
+        );
+
+        let type_registry = app.world.resource::<AppTypeRegistry>().clone();
+        let ron = write_ron(&type_registry, save.clone()).unwrap();
+        let mut loaded = DialogueSave::default();
+        loaded.apply(&*read_ron(&type_registry, ron).unwrap());
+        assert_eq!(loaded, save);
+        assert_eq!(loaded.section, "キツネの森");
+
+        let restored = app
+            .world
+            .spawn((
+                MessageWindow {
+                    name: "test".to_string(),
+                },
+                WindowState::Waiting,
+                RestoredWait::from_save(&loaded),
+            ))
+            .id();
+        let text_box = app
+            .world
+            .spawn((
+                TextBox {
+                    name: "test".to_string(),
+                },
+                feed_trigger(1.5),
+            ))
+            .set_parent(restored)
+            .id();
+        app.update();
+        let remaining = app
+            .world
+            .get::<WaitFeedingTrigger>(text_box)
+            .unwrap()
+            .timer
+            .remaining_secs();
+        assert!((remaining - 0.4).abs() < 1e-3);
+        assert!(app.world.get::<RestoredWait>(restored).is_none());
+    }
+}
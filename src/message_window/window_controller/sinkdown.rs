@@ -1,9 +1,16 @@
+use super::pause::Paused;
+use super::popup::{pose_window, screen_size};
 use super::*;
 use crate::message_window::*;
+use bevy::window::PrimaryWindow;
 
 #[derive(Component, Debug)]
-pub struct ScalingDown {
-    pub sub_per_sec: f32,
+pub struct SinkingDown {
+    pub sink_type: SinkDownType,
+    pub elapsed: f32,
+    pub origin: Vec3,
+    pub text_visible: f32,
+    pub frame_visible: f32,
 }
 
 #[derive(Reflect, Default, Debug)]
@@ -26,7 +33,7 @@ pub struct GoSinking {
 #[allow(clippy::type_complexity)]
 pub fn setup_window_sink(
     mut commands: Commands,
-    text_query: Query<(Entity, &TypingTimer), (With<Current>, With<MessageTextChar>)>,
+    line_text_query: Query<(Entity, &LineGlyphs), With<LineText>>,
     text_box_query: Query<(Entity, &GlobalTransform, &Sprite), (With<Current>, With<TextBox>)>,
     mut mw_query: Query<
         (Entity, &mut WindowState, &WaitBrakerStyle),
@@ -34,20 +41,24 @@ pub fn setup_window_sink(
     >,
     parents: Query<&Parent>,
     mut events: EventReader<BMSEvent>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
     type_registry: Res<AppTypeRegistry>,
 ) {
     for event_wrapper in events.iter() {
         if let Some(SinkDownWindow { sink_type: sdt }) = event_wrapper.get_opt::<SinkDownWindow>() {
             for (mw_entity, mut ws, wbs) in &mut mw_query {
+                if !event_wrapper.is_for(mw_entity) {
+                    continue;
+                }
                 match wbs {
                     WaitBrakerStyle::Auto { wait_sec: base_sec } => {
-                        let count: f32 = text_query
+                        let count: f32 = line_text_query
                             .iter()
-                            .filter(|(tx_entity, _)| {
-                                parents.iter_ancestors(*tx_entity).any(|x| x == mw_entity)
+                            .filter(|(lt_entity, _)| {
+                                parents.iter_ancestors(*lt_entity).any(|x| x == mw_entity)
                             })
-                            .map(|(_, tt)| tt.timer.remaining_secs())
-                            .sum();
+                            .map(|(_, lg)| lg.remaining_secs())
+                            .fold(0., f32::max);
                         commands.entity(mw_entity).insert(WaitSinkingTrigger {
                             sink_type: sdt,
                             timer: Timer::from_seconds(base_sec + count, TimerMode::Once),
@@ -75,6 +86,8 @@ pub fn setup_window_sink(
                             );
                             commands.entity(target_tb).insert(wig);
                         }
+                        lifecycle_event
+                            .send(WindowLifecycleEvent::WaitingInput { window: mw_entity });
                     }
                 }
                 *ws = WindowState::Waiting
@@ -94,9 +107,10 @@ pub fn trigger_window_sink_by_event(
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn trigger_window_sink_by_time(
     mut commands: Commands,
-    mut mw_query: Query<(Entity, &mut WaitSinkingTrigger), With<MessageWindow>>,
+    mut mw_query: Query<(Entity, &mut WaitSinkingTrigger), (With<MessageWindow>, Without<Paused>)>,
     time: Res<Time>,
     mut events: EventWriter<GoSinking>,
 ) {
@@ -111,46 +125,201 @@ pub fn trigger_window_sink_by_time(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+pub fn close_window(
+    mut commands: Commands,
+    mw_query: Query<(&SinkDownType, &WindowState, &Speaker), With<MessageWindow>>,
+    line_query: Query<(Entity, &Transform), With<MessageTextLine>>,
+    line_text_query: Query<(&LineGlyphs, &Text, &Parent), With<LineText>>,
+    parent_query: Query<&Parent>,
+    mut close_events: EventReader<CloseWindowEvent>,
+    mut gs_writer: EventWriter<GoSinking>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
+    mut backlog: ResMut<Backlog>,
+) {
+    for CloseWindowEvent { window } in &mut close_events {
+        let Ok((st, ws, speaker)) = mw_query.get(*window) else {
+            continue;
+        };
+        if *ws == WindowState::SinkingDown {
+            continue;
+        }
+        match st {
+            // Fixは窓を残す閉じ方なので、閉じるよう頼まれたときはその場で消す。
+            SinkDownType::Fix => {
+                record_remaining_lines(
+                    *window,
+                    &line_query,
+                    &line_text_query,
+                    &parent_query,
+                    &mut backlog,
+                    &speaker.name,
+                );
+                commands.entity(*window).despawn_recursive();
+                lifecycle_event.send(WindowLifecycleEvent::Closed { window: *window });
+            }
+            _ => gs_writer.send(GoSinking {
+                target: Some(*window),
+                sink_type: *st,
+            }),
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn start_window_sink(
     mut commands: Commands,
-    mut mw_query: Query<(Entity, &mut WindowState), With<MessageWindow>>,
+    mut mw_query: Query<(Entity, &mut WindowState, &Transform, &Speaker), With<MessageWindow>>,
+    line_query: Query<(Entity, &Transform), With<MessageTextLine>>,
+    line_text_query: Query<(&LineGlyphs, &Text, &Parent), With<LineText>>,
+    parent_query: Query<&Parent>,
     mut events: EventReader<GoSinking>,
+    mut backlog: ResMut<Backlog>,
 ) {
     for GoSinking {
         target: entity_opt,
         sink_type: st,
     } in &mut events
     {
-        for (mw_entity, mut ws) in &mut mw_query {
-            if let Some(entity) = *entity_opt {
-                if entity == mw_entity {
-                    match st {
-                        SinkDownType::Scale { sec: s } => {
-                            commands.entity(entity).insert(ScalingDown {
-                                sub_per_sec: 1.0 / s,
-                            });
-                            *ws = WindowState::SinkingDown;
-                        }
-                        SinkDownType::Fix => *ws = WindowState::Fixed,
+        for (mw_entity, mut ws, tf, speaker) in &mut mw_query {
+            if *entity_opt != Some(mw_entity) {
+                continue;
+            }
+            match st {
+                SinkDownType::Fix => *ws = WindowState::Fixed,
+                _ => {
+                    if *ws != WindowState::SinkingDown {
+                        record_remaining_lines(
+                            mw_entity,
+                            &line_query,
+                            &line_text_query,
+                            &parent_query,
+                            &mut backlog,
+                            &speaker.name,
+                        );
                     }
+                    commands.entity(mw_entity).insert(SinkingDown {
+                        sink_type: *st,
+                        elapsed: 0.,
+                        origin: tf.translation,
+                        text_visible: 1.,
+                        frame_visible: 1.,
+                    });
+                    *ws = WindowState::SinkingDown;
                 }
             }
         }
     }
 }
 
-pub fn scaling_down(
+#[allow(clippy::type_complexity)]
+pub fn sinking_down(
     mut commands: Commands,
-    mut mw_query: Query<(Entity, &mut Transform, &ScalingDown)>,
+    mut mw_query: Query<(Entity, &mut Transform, &mut SinkingDown), Without<Paused>>,
+    children_query: Query<&Children>,
+    mut fade_query: Query<(
+        Option<&mut Text>,
+        Option<&mut Sprite>,
+        Option<&MessageTextChar>,
+    )>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
     time: Res<Time>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
 ) {
-    for (entity, mut tf, ScalingDown { sub_per_sec: aps }) in &mut mw_query {
-        if tf.scale.x <= 0.0 {
-            tf.scale = Vec3::new(0., 0., 0.);
+    let screen = screen_size(&window_query);
+    for (entity, mut tf, mut sinking) in &mut mw_query {
+        sinking.elapsed += time.delta_seconds();
+        let elapsed = sinking.elapsed;
+        let progress = |sec: f32, from: f32| {
+            if sec > 0. {
+                ((elapsed - from) / sec).clamp(0., 1.)
+            } else {
+                1.
+            }
+        };
+        let (text_visible, frame_visible, is_finished) = match sinking.sink_type {
+            SinkDownType::Fix => (1., 1., true),
+            SinkDownType::Dissolve {
+                text_sec: ts,
+                frame_sec: fs,
+                easing: e,
+            } => {
+                let text_visible = e.apply(1. - progress(ts, 0.));
+                let frame_t = progress(fs, ts);
+                (text_visible, e.apply(1. - frame_t), frame_t >= 1.)
+            }
+            SinkDownType::Scale { sec: s, easing: e } => {
+                let popup = PopupType::Scale { sec: s, easing: e };
+                let t = progress(s, 0.);
+                pose_window(&popup, 1. - t, sinking.origin, screen, &mut tf);
+                (1., 1., t >= 1.)
+            }
+            SinkDownType::Fade { sec: s, easing: e } => {
+                let popup = PopupType::Fade { sec: s, easing: e };
+                let t = progress(s, 0.);
+                let alpha = pose_window(&popup, 1. - t, sinking.origin, screen, &mut tf);
+                (alpha, alpha, t >= 1.)
+            }
+            SinkDownType::ScaleFade { sec: s, easing: e } => {
+                let popup = PopupType::ScaleFade { sec: s, easing: e };
+                let t = progress(s, 0.);
+                let alpha = pose_window(&popup, 1. - t, sinking.origin, screen, &mut tf);
+                (alpha, alpha, t >= 1.)
+            }
+            SinkDownType::Slide {
+                sec: s,
+                to: edge,
+                easing: e,
+            } => {
+                let popup = PopupType::Slide {
+                    sec: s,
+                    from: edge,
+                    easing: e,
+                };
+                let t = progress(s, 0.);
+                pose_window(&popup, 1. - t, sinking.origin, screen, &mut tf);
+                (1., 1., t >= 1.)
+            }
+        };
+        if is_finished {
             commands.entity(entity).despawn_recursive();
-        } else {
-            tf.scale.x -= time.delta_seconds() * aps;
-            tf.scale.y -= time.delta_seconds() * aps;
+            lifecycle_event.send(WindowLifecycleEvent::Closed { window: entity });
+            continue;
+        }
+        let ratio = |next: f32, prev: f32| {
+            if prev > 0. {
+                next.clamp(0., 1.) / prev
+            } else {
+                0.
+            }
         };
+        let text_ratio = ratio(text_visible, sinking.text_visible);
+        let frame_ratio = ratio(frame_visible, sinking.frame_visible);
+        sinking.text_visible = text_visible.clamp(0., 1.);
+        sinking.frame_visible = frame_visible.clamp(0., 1.);
+        if text_ratio == 1. && frame_ratio == 1. {
+            continue;
+        }
+        for target in std::iter::once(entity).chain(children_query.iter_descendants(entity)) {
+            let Ok((text_opt, sprite_opt, char_opt)) = fade_query.get_mut(target) else {
+                continue;
+            };
+            let is_text = text_opt.is_some() || char_opt.is_some();
+            let r = if is_text {
+                text_ratio * frame_ratio
+            } else {
+                frame_ratio
+            };
+            if let Some(mut text) = text_opt {
+                for section in text.sections.iter_mut() {
+                    let alpha = section.style.color.a();
+                    section.style.color.set_a(alpha * r);
+                }
+            }
+            if let Some(mut sprite) = sprite_opt {
+                let alpha = sprite.color.a();
+                sprite.color.set_a(alpha * r);
+            }
+        }
     }
 }
@@ -0,0 +1,289 @@
+use super::bubble::BubbleFrame;
+use super::nine_slice::{NineSliceBackground, NineSlicePiece};
+use super::*;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::{serde::TypedReflectDeserializer, TypeUuid},
+    utils::BoxedFuture,
+};
+use serde::de::DeserializeSeed;
+
+// 書かなかった項目は窓の設定のままになる。OpenWindowEventのoverridesにも同じ形で書く。
+#[derive(Reflect, Default, Debug, Clone, PartialEq, TypeUuid)]
+#[uuid = "3f0c8a52-6d1e-4b7a-9e2f-58c4d1a7b690"]
+pub struct WindowTheme {
+    #[reflect(default)]
+    pub font_paths: Option<Vec<String>>,
+    #[reflect(default)]
+    pub font_size: Option<f32>,
+    #[reflect(default)]
+    pub font_color: Option<Color>,
+    #[reflect(default)]
+    pub background_path: Option<String>,
+    #[reflect(default)]
+    pub nine_slice: Option<NineSlice>,
+    #[reflect(default)]
+    pub background_padding: Option<Vec2>,
+    #[reflect(default)]
+    pub main_box_origin: Option<Vec2>,
+    #[reflect(default)]
+    pub main_box_size: Option<Vec2>,
+    #[reflect(default)]
+    pub popup: Option<PopupType>,
+    #[reflect(default)]
+    pub sinkdown: Option<SinkDownType>,
+    #[reflect(default)]
+    pub typing_timing: Option<TypingTiming>,
+    #[reflect(default)]
+    pub writing: Option<WritingStyle>,
+    #[reflect(default)]
+    pub feeding: Option<FeedingStyle>,
+    #[reflect(default)]
+    pub wait_breaker: Option<WaitBrakerStyle>,
+}
+
+pub struct WindowThemeLoader {
+    type_registry: AppTypeRegistry,
+}
+
+impl FromWorld for WindowThemeLoader {
+    fn from_world(world: &mut World) -> Self {
+        WindowThemeLoader {
+            type_registry: world.resource::<AppTypeRegistry>().clone(),
+        }
+    }
+}
+
+impl AssetLoader for WindowThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let theme = read_theme(&self.type_registry, bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(theme));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mwtheme.ron"]
+    }
+}
+
+fn read_theme(type_registry: &AppTypeRegistry, bytes: &[u8]) -> Result<WindowTheme, ron::Error> {
+    let reg = type_registry.read();
+    let Some(registration) = reg.get(std::any::TypeId::of::<WindowTheme>()) else {
+        return Err(ron::Error::Message(
+            "WindowTheme is not registered".to_string(),
+        ));
+    };
+    let reflect_deserializer = TypedReflectDeserializer::new(registration, &reg);
+    let mut deserializer = ron::de::Deserializer::from_bytes(bytes)?;
+    let value = reflect_deserializer.deserialize(&mut deserializer)?;
+    WindowTheme::from_reflect(&*value)
+        .ok_or_else(|| ron::Error::Message("invalid window theme".to_string()))
+}
+
+// 窓を開いたときの設定を残しておき、テーマが変わるたびにそれと重ね直す。
+#[derive(Component, Debug)]
+pub struct WindowThemeRef {
+    pub handle: Option<Handle<WindowTheme>>,
+    pub base: OpenWindowEvent,
+    pub is_applied: bool,
+}
+
+#[derive(Reflect, Default, Debug)]
+pub struct ChangeTheme {
+    pub path: String,
+}
+
+impl OpenWindowEvent {
+    // 窓の設定の上にテーマを重ね、さらにその上にoverridesで書いた項目を重ねる。
+    pub fn with_theme(&self, theme: &WindowTheme) -> OpenWindowEvent {
+        let own = &self.overrides;
+        OpenWindowEvent {
+            font_paths: pick(&self.font_paths, &theme.font_paths, &own.font_paths),
+            font_size: pick(&self.font_size, &theme.font_size, &own.font_size),
+            font_color: pick(&self.font_color, &theme.font_color, &own.font_color),
+            background_path: pick(
+                &self.background_path,
+                &theme.background_path,
+                &own.background_path,
+            ),
+            nine_slice: own.nine_slice.or(theme.nine_slice).or(self.nine_slice),
+            background_padding: pick(
+                &self.background_padding,
+                &theme.background_padding,
+                &own.background_padding,
+            ),
+            main_box_origin: pick(
+                &self.main_box_origin,
+                &theme.main_box_origin,
+                &own.main_box_origin,
+            ),
+            main_box_size: pick(
+                &self.main_box_size,
+                &theme.main_box_size,
+                &own.main_box_size,
+            ),
+            popup: pick(&self.popup, &theme.popup, &own.popup),
+            sinkdown: pick(&self.sinkdown, &theme.sinkdown, &own.sinkdown),
+            typing_timing: pick(
+                &self.typing_timing,
+                &theme.typing_timing,
+                &own.typing_timing,
+            ),
+            writing: pick(&self.writing, &theme.writing, &own.writing),
+            feeding: pick(&self.feeding, &theme.feeding, &own.feeding),
+            wait_breaker: pick(&self.wait_breaker, &theme.wait_breaker, &own.wait_breaker),
+            ..self.clone()
+        }
+    }
+}
+
+fn pick<T: Clone>(base: &T, theme: &Option<T>, own: &Option<T>) -> T {
+    own.as_ref().or(theme.as_ref()).unwrap_or(base).clone()
+}
+
+// 読み込み直したテーマやスクリプトで切り替えたテーマを、開いている窓に当て直す。
+// 枠の作り(九分割かどうか、吹き出しかどうか)は開いたときのまま変えない。
+#[allow(clippy::type_complexity)]
+pub fn apply_window_themes(
+    mut commands: Commands,
+    mut mw_query: Query<
+        (
+            Entity,
+            &mut WindowThemeRef,
+            &mut PopupType,
+            &mut SinkDownType,
+            &mut WaitBrakerStyle,
+            Option<&mut Handle<Image>>,
+            Option<&mut NineSliceBackground>,
+            Option<&mut BubbleFrame>,
+        ),
+        With<MessageWindow>,
+    >,
+    mut text_box_query: Query<
+        (
+            &Parent,
+            &mut TypeTextConfig,
+            &mut FeedingStyle,
+            &mut Transform,
+            &mut Sprite,
+        ),
+        With<TextBox>,
+    >,
+    piece_query: Query<(Entity, &Parent), With<NineSlicePiece>>,
+    mut theme_events: EventReader<AssetEvent<WindowTheme>>,
+    mut bms_reader: EventReader<BMSEvent>,
+    themes: Res<Assets<WindowTheme>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event_wrapper in bms_reader.iter() {
+        if let Some(ChangeTheme { path }) = event_wrapper.get_opt::<ChangeTheme>() {
+            for (mw_entity, mut theme_ref, ..) in &mut mw_query {
+                if event_wrapper.is_for(mw_entity) {
+                    theme_ref.handle = Some(asset_server.load(path.as_str()));
+                    theme_ref.is_applied = false;
+                }
+            }
+        }
+    }
+    for event in theme_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            for (_, mut theme_ref, ..) in &mut mw_query {
+                if theme_ref.handle.as_ref() == Some(handle) {
+                    theme_ref.is_applied = false;
+                }
+            }
+        }
+    }
+    for (
+        mw_entity,
+        mut theme_ref,
+        mut popup,
+        mut sinkdown,
+        mut wbs,
+        image_opt,
+        bg_opt,
+        frame_opt,
+    ) in &mut mw_query
+    {
+        if theme_ref.is_applied {
+            continue;
+        }
+        let Some(theme) = theme_ref.handle.as_ref().and_then(|h| themes.get(h)) else {
+            continue;
+        };
+        let config = theme_ref.base.with_theme(theme);
+        *popup = config.popup;
+        *sinkdown = config.sinkdown;
+        *wbs = config.wait_breaker;
+        match (bg_opt, image_opt) {
+            (Some(mut bg), _) => {
+                if let Some(slice) = config.nine_slice {
+                    bg.slice = slice;
+                }
+                bg.texture = asset_server.load(config.background_path.clone());
+                bg.rect = config.frame_rect();
+                bg.is_built = false;
+                for (piece_entity, piece_parent) in &piece_query {
+                    if piece_parent.get() == mw_entity {
+                        commands.entity(piece_entity).despawn_recursive();
+                    }
+                }
+            }
+            (None, Some(mut image)) => {
+                *image = asset_server.load(config.background_path.clone());
+            }
+            _ => {}
+        }
+        if let Some(mut frame) = frame_opt {
+            frame.rect = config.frame_rect();
+        }
+        for (tb_parent, mut tb_config, mut feeding, mut tb_tf, mut tb_sp) in &mut text_box_query {
+            if tb_parent.get() != mw_entity {
+                continue;
+            }
+            tb_config.default_style = config.default_style(&asset_server);
+            // 文字の設定を重ねている途中なら、重ねる前の一番下の設定を差し替える。
+            let style = tb_config.default_style.clone();
+            match tb_config.style_stack.first_mut() {
+                Some(base_style) => *base_style = style,
+                None => tb_config.restore(style),
+            }
+            tb_config.typing_timing = config.typing_timing;
+            *feeding = config.feeding;
+            tb_tf.translation = config.main_box_origin.extend(tb_tf.translation.z);
+            tb_sp.custom_size = Some(config.main_box_size);
+        }
+        theme_ref.is_applied = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_equal_to_default() {
+        let theme = WindowTheme {
+            font_size: Some(30.),
+            writing: Some(WritingStyle::Put),
+            ..default()
+        };
+        let config = OpenWindowEvent {
+            overrides: WindowTheme {
+                font_size: Some(OpenWindowEvent::default().font_size),
+                ..default()
+            },
+            ..default()
+        };
+        let themed = config.with_theme(&theme);
+        assert_eq!(themed.font_size, 27.);
+        assert_eq!(themed.writing, WritingStyle::Put);
+        assert_eq!(themed.main_box_size, config.main_box_size);
+    }
+}
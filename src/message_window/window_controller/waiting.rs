@@ -34,11 +34,15 @@ pub fn simple_wait(
         (With<Current>, With<TextBox>),
     >,
     mut bms_reader: EventReader<BMSEvent>,
+    mut lifecycle_event: EventWriter<WindowLifecycleEvent>,
     type_registry: Res<AppTypeRegistry>,
 ) {
     for event_wrapper in bms_reader.iter() {
         if event_wrapper.get_opt::<SimpleWait>() == Some(SimpleWait) {
             for (mw_entity, mut ws, wbs) in &mut window_query {
+                if !event_wrapper.is_for(mw_entity) {
+                    continue;
+                }
                 if let WaitBrakerStyle::Input { .. } = wbs {
                     for (tb_entity, tb_tf, tb_sp, parent) in &text_box_query {
                         if parent.get() == mw_entity {
@@ -55,6 +59,7 @@ pub fn simple_wait(
                         }
                     }
                     *ws = WindowState::Waiting;
+                    lifecycle_event.send(WindowLifecycleEvent::WaitingInput { window: mw_entity });
                 }
             }
         }
@@ -73,10 +78,11 @@ pub fn restart_typing(
         }) = event_wrapper.get_opt::<BreakWait>()
         {
             for (mw_entity, mut ws, wbs) in &mut window_query {
-                if let Ok(tb_parent) = text_box_query.get(tb_entity) {
-                    if tb_parent.get() == mw_entity && WindowState::Waiting == *ws {
-                        *ws = WindowState::Typing;
-                    }
+                if text_box_query.get(tb_entity).map(|p| p.get()) != Ok(mw_entity) {
+                    continue;
+                }
+                if WindowState::Waiting == *ws {
+                    *ws = WindowState::Typing;
                 }
                 if let WaitBrakerStyle::Input {
                     icon_entity: Some(ic_entity),
@@ -121,7 +127,7 @@ pub fn waiting_icon_setting(
 #[allow(clippy::type_complexity)]
 pub fn settle_wating_icon(
     window_query: Query<(Entity, &WindowState, &WaitBrakerStyle), With<MessageWindow>>,
-    text_box_query: Query<(Entity, &Parent, &TypeTextConfig), With<TextBox>>,
+    text_box_query: Query<(Entity, &Parent), With<TextBox>>,
     mut icon_query: Query<
         (&mut Transform, &mut WaitingIcon),
         (Without<MessageTextLine>, Without<MessageTextChar>),
@@ -140,16 +146,15 @@ pub fn settle_wating_icon(
                 } = &mut *wi;
                 if *ws == WindowState::Waiting {
                     if *settled {
-                        return;
+                        continue;
                     }
-                    if let Some((tb_entity, _, config)) =
-                        text_box_query.iter().find(|(_, p, _)| p.get() == mw_entity)
+                    if let Some((tb_entity, _)) =
+                        text_box_query.iter().find(|(_, p)| p.get() == mw_entity)
                     {
                         let (_, _, last_x, last_y, _) =
                             initialize_typing_data(&last_data, tb_entity);
                         if *move_flag {
-                            ic_tf.translation =
-                                Vec3::new(last_x + config.text_style.font_size, last_y, 1.);
+                            ic_tf.translation = Vec3::new(last_x, last_y, 1.);
                         }
                     }
                     *settled = true;
@@ -166,10 +171,21 @@ pub fn skip_typing_or_next(
     mut commands: Commands,
     mut waiting_text_query: Query<
         (Entity, &mut Visibility, &mut Transform, &Parent),
-        With<MessageTextChar>,
+        (With<MessageTextChar>, Without<TypingStyle>),
+    >,
+    mut typing_texts_query: Query<
+        (
+            Entity,
+            &TypingStyle,
+            &mut Transform,
+            Option<&mut Text>,
+            Option<&mut Sprite>,
+            &Parent,
+        ),
+        (With<MessageTextChar>, Without<TextBox>),
     >,
-    mut typing_texts_query: Query<(Entity, &mut TypingStyle, &Parent), With<MessageTextChar>>,
-    window_query: Query<&WindowState, With<MessageWindow>>,
+    mut line_text_query: Query<(&mut LineGlyphs, &mut Text, &Parent), Without<MessageTextChar>>,
+    window_query: Query<(&WindowState, &WaitBrakerStyle), With<MessageWindow>>,
     text_box_query: Query<(&GlobalTransform, &Sprite, &Parent), With<TextBox>>,
     line_query: Query<(Entity, &Parent), With<MessageTextLine>>,
     mut icon_query: Query<(Entity, &mut Visibility), (With<WaitingIcon>, Without<MessageTextChar>)>,
@@ -182,20 +198,32 @@ pub fn skip_typing_or_next(
             target_text_box: Some(tb_entity),
         }) = event_wrapper.get_opt::<InputForSkipping>()
         {
-            if let Ok((_, _, parent)) = text_box_query.get(tb_entity) {
-                if window_query.get(parent.get()) != Ok(&WindowState::Waiting) {
-                    return;
-                }
+            let Ok((_, _, tb_parent)) = text_box_query.get(tb_entity) else {
+                continue;
+            };
+            let Ok((ws, wbs)) = window_query.get(tb_parent.get()) else {
+                continue;
+            };
+            if *ws != WindowState::Waiting {
+                continue;
             }
             let mut typed_count = 0usize;
             let mut text_count = 0usize;
-            for (text_entity, ts, t_parent) in &mut typing_texts_query {
+            for (text_entity, ts, mut tf, mut text_opt, mut sprite_opt, t_parent) in
+                &mut typing_texts_query
+            {
                 if line_query.get(t_parent.get()).map(|x| x.1.get()) == Ok(tb_entity) {
-                    match *ts {
+                    match ts {
                         TypingStyle::Typed => {
                             typed_count += 1;
                         }
                         _ => {
+                            complete_typing(
+                                ts,
+                                &mut tf,
+                                text_opt.as_deref_mut(),
+                                sprite_opt.as_deref_mut(),
+                            );
                             commands.entity(text_entity).remove::<TypingStyle>();
                             commands.entity(text_entity).insert(TypingStyle::Typed);
                         }
@@ -203,15 +231,39 @@ pub fn skip_typing_or_next(
                     text_count += 1;
                 }
             }
-            for (ic_entity, mut ic_vis) in &mut icon_query {
-                *ic_vis = Visibility::Inherited;
-                commands.entity(ic_entity).remove::<TypingTimer>();
+            for (mut lg, mut text, lt_parent) in &mut line_text_query {
+                if line_query.get(lt_parent.get()).map(|x| x.1.get()) == Ok(tb_entity) {
+                    let started = lg.glyphs[..lg.cursor]
+                        .iter()
+                        .filter(|g| !g.is_separated)
+                        .collect::<Vec<_>>();
+                    typed_count += started
+                        .iter()
+                        .filter(|g| g.reveal == GlyphReveal::Typed)
+                        .count();
+                    text_count += started.len();
+                    complete_line_glyphs(&mut lg, &mut text);
+                }
+            }
+            if let WaitBrakerStyle::Input {
+                icon_entity: Some(ic_entity),
+                ..
+            } = wbs
+            {
+                if let Ok((ic_entity, mut ic_vis)) = icon_query.get_mut(*ic_entity) {
+                    *ic_vis = Visibility::Inherited;
+                    commands.entity(ic_entity).remove::<TypingTimer>();
+                }
             }
             if let Ok((tb_tf, tb_sp, _)) = text_box_query.get(tb_entity) {
                 if text_count == typed_count {
                     if let Ok(ref_value) = read_ron(&type_registry, ron.clone()) {
-                        commands.add(|w: &mut World| {
-                            w.send_event(BMSEvent { value: ref_value });
+                        let window = Some(tb_parent.get());
+                        commands.add(move |w: &mut World| {
+                            w.send_event(BMSEvent {
+                                value: ref_value,
+                                window,
+                            });
                         })
                     }
                 } else {
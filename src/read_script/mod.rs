@@ -9,14 +9,21 @@ use bevy::{
     },
     utils::BoxedFuture,
 };
+use parse_bms::read_bms;
 use serde::{de::DeserializeSeed, Deserialize};
 
 #[derive(Event)]
 pub struct BMSEvent {
     pub value: Box<dyn Reflect>,
+    pub window: Option<Entity>,
 }
 
 impl BMSEvent {
+    // 送り元の窓がないイベントは全ての窓に向けたものとして扱う。
+    pub fn is_for(&self, window: Entity) -> bool {
+        self.window.is_none_or(|w| w == window)
+    }
+
     pub fn get<T: Default + Reflect>(&self) -> T {
         let mut my_data = <T>::default();
         my_data.apply(&*self.value);
@@ -32,9 +39,11 @@ impl BMSEvent {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Reflect)]
 pub enum Order {
-    Type { character: char },
+    Type { character: String },
+    Image { path: String },
+    ButtonPrompt { action: String },
     CarriageReturn,
     PageFeed,
     ThroghEvent { ron: String },
@@ -44,6 +53,7 @@ pub enum Order {
 pub struct LoadedScript {
     pub bms_handle: Handle<BMWScript>,
     pub order_list: Option<Vec<Order>>,
+    pub section: String,
 }
 
 #[derive(Debug, Deserialize, TypeUuid, TypePath)]
@@ -82,12 +92,28 @@ pub fn script_on_load(
         if loaded_script.order_list.is_none() {
             let script_opt = script_assets.get(&loaded_script.bms_handle);
             if let Some(bms) = script_opt {
-                loaded_script.order_list = Some(parse_script(bms.script.clone()));
+                let mut section_map = read_bms(&bms.script);
+                let orders = section_map
+                    .remove(&loaded_script.section)
+                    .unwrap_or_default();
+                loaded_script.order_list = Some(orders.into_iter().rev().collect());
             }
         }
     }
 }
 
+pub fn script_characters<S: AsRef<str>>(script: S) -> Vec<char> {
+    read_bms(script)
+        .into_values()
+        .flatten()
+        .filter_map(|o| match o {
+            Order::Type { character: c } => Some(c),
+            _ => None,
+        })
+        .flat_map(|c| c.chars().collect::<Vec<char>>())
+        .collect()
+}
+
 pub fn read_ron<S: AsRef<str>>(
     type_registry: &AppTypeRegistry,
     ron: S,
@@ -108,44 +134,150 @@ pub fn write_ron<R: Reflect>(
     ron::ser::to_string_pretty(&serializer, ron::ser::PrettyConfig::default())
 }
 
-//-- 以下は仮設定
-pub fn parse_script(base: String) -> Vec<Order> {
-    base.chars()
-        .map(|c| match c {
-            '\t' => Order::PageFeed,
-            '\n' => Order::CarriageReturn,
-            '-' => Order::ThroghEvent {
-                ron: r#"{
-    "bevy_message_window::message_window::bms_event::FontSizeChange": (
-        size: 27.0,
-)}"#
-                .to_string(),
-            },
-            '+' => Order::ThroghEvent {
-                ron: r#"{
-    "bevy_message_window::message_window::bms_event::FontSizeChange": (
-        size: 35.0,
-),}"#
-                    .to_string(),
-            },
-            '!' => Order::ThroghEvent {
-                ron: r#"{
-    "bevy_message_window::message_window::window_controller::sinkdown::SinkDownWindow": (
-	sink_type: Scale(
-			sec: 0.8,
-		),
-    ),
-}"#
-                .to_string(),
-            },
-            '?' => Order::ThroghEvent {
-                ron: r#"{
-    "bevy_message_window::message_window::window_controller::waiting::SimpleWait":(),
-}"#
-                .to_string(),
-            },
-            _ => Order::Type { character: c },
-        })
-        .rev()
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_window::window_controller::{
+        sinkdown::SinkDownWindow, theme::ChangeTheme, Easing, SinkDownType,
+    };
+
+    // 実際のローダーを通して、窓に渡る順番に並べ直した命令列を返す。
+    fn load_orders(script: &str, section: &str) -> Vec<Order> {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<BMWScript>()
+            .add_systems(Update, script_on_load);
+        let bms_handle = app
+            .world
+            .resource_mut::<Assets<BMWScript>>()
+            .add(BMWScript {
+                script: script.to_string(),
+            });
+        let window = app
+            .world
+            .spawn(LoadedScript {
+                bms_handle,
+                order_list: None,
+                section: section.to_string(),
+            })
+            .id();
+        app.update();
+        let loaded = app.world.get::<LoadedScript>(window).unwrap();
+        loaded
+            .order_list
+            .clone()
+            .unwrap()
+            .into_iter()
+            .rev()
+            .collect()
+    }
+
+    fn event_rons(orders: &[Order]) -> Vec<&str> {
+        orders
+            .iter()
+            .filter_map(|o| match o {
+                Order::ThroghEvent { ron } => Some(ron.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_load_writing_tag() {
+        let orders = load_orders("<h1>森</h1><fade sec=0.5>あ</fade>", "森");
+        let rons = event_rons(&orders);
+        assert_eq!(rons.len(), 2);
+        assert!(rons[0].contains("PushWritingStyle\": (writing: Fade(sec: 0.5))"));
+        assert!(rons[1].ends_with("PopTextStyle\": ()}"));
+        assert_eq!(script_characters("<fade sec=0.5>あ</fade>"), vec!['あ']);
+    }
+
+    #[test]
+    fn test_load_effect_tag() {
+        let orders = load_orders("<shake>あ</shake><rainbow>い</rainbow>", "");
+        let rons = event_rons(&orders);
+        assert!(rons[0].contains("PushTextEffect\": (effect: Shake(amp: 1.5))"));
+        assert!(rons[1].ends_with("PopTextStyle\": ()}"));
+        assert!(rons[2].contains("(effect: Rainbow(speed: 0.5, length: 400.0))"));
+        assert_eq!(script_characters("<shake>あ</shake>"), vec!['あ']);
+    }
+
+    #[test]
+    fn test_load_text_style_tag() {
+        let orders = load_orders(
+            "<color value=\"#ffb070\"><font name=\"Noto\">あ</font></color><reset>",
+            "",
+        );
+        let rons = event_rons(&orders);
+        assert!(rons[0].contains("FontColorChange\": (color: Rgba(red: 1.0,"));
+        assert!(rons[1].ends_with("FontChange\": (name: \"Noto\")}"));
+        assert!(rons[4].ends_with("ResetTextStyle\": ()}"));
+    }
+
+    #[test]
+    fn test_load_inline_image() {
+        let orders = load_orders("あ![key](icons/key.png)", "");
+        assert_eq!(
+            orders[1],
+            Order::Image {
+                path: "icons/key.png".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_button_prompt() {
+        let orders = load_orders("<button action=advance/>で進む", "");
+        assert_eq!(
+            orders[0],
+            Order::ButtonPrompt {
+                action: "advance".to_string()
+            }
+        );
+        assert_eq!(script_characters("<button action=advance/>で"), vec!['で']);
+    }
+
+    #[test]
+    fn test_load_sinkdown_script() {
+        let orders = load_orders(
+            r#"あ<script>{"bevy_message_window::message_window::window_controller::sinkdown::SinkDownWindow": (sink_type: Fade(sec: 0.8, easing: InQuad))}</script>"#,
+            "",
+        );
+        let registry = AppTypeRegistry::default();
+        {
+            let mut reg = registry.write();
+            reg.register::<SinkDownWindow>();
+            reg.register::<SinkDownType>();
+            reg.register::<Easing>();
+        }
+        let event = BMSEvent {
+            value: read_ron(&registry, event_rons(&orders)[0]).unwrap(),
+            window: None,
+        };
+        let sink = event.get_opt::<SinkDownWindow>().unwrap();
+        assert_eq!(
+            sink.sink_type,
+            SinkDownType::Fade {
+                sec: 0.8,
+                easing: Easing::InQuad
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_change_theme_script() {
+        let orders = load_orders(
+            r#"<script>{"bevy_message_window::message_window::window_controller::theme::ChangeTheme": (path: "themes/default.mwtheme.ron")}</script>あ"#,
+            "",
+        );
+        let registry = AppTypeRegistry::default();
+        registry.write().register::<ChangeTheme>();
+        let event = BMSEvent {
+            value: read_ron(&registry, event_rons(&orders)[0]).unwrap(),
+            window: None,
+        };
+        let change = event.get_opt::<ChangeTheme>().unwrap();
+        assert_eq!(change.path, "themes/default.mwtheme.ron");
+        assert!(event.get_opt::<SinkDownWindow>().is_none());
+    }
 }
@@ -6,6 +6,7 @@ use nom::multi::*;
 use nom::sequence::*;
 use nom::*;
 use std::collections::HashMap;
+use unicode_segmentation::UnicodeSegmentation;
 
 use super::Order;
 
@@ -16,7 +17,8 @@ enum ParsedOrder {
     Empty,
 }
 
-pub fn read_script<S: AsRef<str>>(input: S, template: S)  -> HashMap<String, Vec<Order>> {
+#[allow(dead_code)]
+pub fn read_script<S: AsRef<str>>(_input: S, _template: S)  -> HashMap<String, Vec<Order>> {
     todo!()
 }
 
@@ -45,8 +47,14 @@ fn parse_bms(input: &str) -> Vec<ParsedOrder> {
         section_head,
         next_paragraph,
         throw_event,
+        inline_image,
+        button_prompt,
         next_line,
+        writing_tag,
+        effect_tag,
+        text_style_tag,
         erase_useless_tag,
+        soft_line_break,
         simple_char,
     )));
     if let Ok((_, parsed_order_list)) = bms_parser(input) {
@@ -56,26 +64,15 @@ fn parse_bms(input: &str) -> Vec<ParsedOrder> {
     }
 }
 
-fn escape (input: &str) -> IResult<&str, ParsedOrder> {
-    preceded(char('\\'), alt((
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '\\' }), char('\\')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '<' }), char('<')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '>' }), char('>')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '`' }), char('`')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '{' }), char('{')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '}' }), char('}')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '[' }), char('[')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: ']' }), char(']')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '_' }), char('_')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '*' }), char('*')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '+' }), char('+')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '(' }), char('(')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: ')' }), char(')')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '#' }), char('#')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '.' }), char('.')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '!' }), char('!')),
-        value(ParsedOrder::OrderWrapper(Order::Type{ character: '|' }), char('|')),
-    )))(input)
+fn escape(input: &str) -> IResult<&str, ParsedOrder> {
+    preceded(char('\\'), one_of("\\<>`{}[]_*+()#.!|"))(input).map(|(rem, c)| {
+        (
+            rem,
+            ParsedOrder::OrderWrapper(Order::Type {
+                character: c.to_string(),
+            }),
+        )
+    })
 }
 
 fn erase_useless_tag(input: &str) -> IResult<&str, ParsedOrder> {
@@ -98,15 +95,21 @@ fn next_paragraph(input: &str) -> IResult<&str, ParsedOrder> {
     alt((end_p_tag, more_then_2_lines))(input)
 }
 
+fn soft_line_break(input: &str) -> IResult<&str, ParsedOrder> {
+    value(ParsedOrder::Empty, line_ending)(input)
+}
+
+// 絵文字の結合や濁点などの結合文字もまとめて1文字として扱う。
 fn simple_char(input: &str) -> IResult<&str, ParsedOrder> {
-    take(1usize)(input).map(|(rem, c)| {
-        (
-            rem,
-            ParsedOrder::OrderWrapper(Order::Type {
-                character: c.chars().next().unwrap(),
-            }),
-        )
-    })
+    let Some(grapheme) = input.graphemes(true).next() else {
+        return Err(nom::Err::Error(error::Error::new(input, error::ErrorKind::Eof)));
+    };
+    Ok((
+        &input[grapheme.len()..],
+        ParsedOrder::OrderWrapper(Order::Type {
+            character: grapheme.to_string(),
+        }),
+    ))
 }
 
 fn end_tag_include_slash(input: &str) -> IResult<&str, bool> {
@@ -148,51 +151,207 @@ fn throw_event(input: &str) -> IResult<&str, ParsedOrder> {
     ))
 }
 
+fn inline_image(input: &str) -> IResult<&str, ParsedOrder> {
+    let image = |path: &str| {
+        ParsedOrder::OrderWrapper(Order::Image {
+            path: path.to_string(),
+        })
+    };
+    let markdown = preceded(
+        delimited(tag("!["), take_until("]"), char(']')),
+        delimited(char('('), is_not(")"), char(')')),
+    );
+    let img_tag = delimited(tag("<img"), tag_attributes, alt((tag("/>"), tag(">"))));
+    let img = map_opt(img_tag, |attrs| attrs.get("src").copied());
+    map(alt((markdown, img)), image)(input)
+}
+
+fn button_prompt(input: &str) -> IResult<&str, ParsedOrder> {
+    let button_tag = delimited(tag("<button"), tag_attributes, alt((tag("/>"), tag(">"))));
+    map_opt(button_tag, |attrs| {
+        attrs.get("action").map(|a| {
+            ParsedOrder::OrderWrapper(Order::ButtonPrompt {
+                action: a.to_string(),
+            })
+        })
+    })(input)
+}
+
+const EVENT_PATH: &str = "bevy_message_window::message_window::bms_event";
+
+fn event_order(name: &str, body: &str) -> ParsedOrder {
+    ParsedOrder::OrderWrapper(Order::ThroghEvent {
+        ron: format!(r#"{{"{EVENT_PATH}::{name}": ({body})}}"#),
+    })
+}
+
+fn tag_attributes(input: &str) -> IResult<&str, HashMap<&str, &str>> {
+    let quoted = alt((
+        delimited(char('"'), take_until("\""), char('"')),
+        delimited(char('\''), take_until("'"), char('\'')),
+    ));
+    let attr_value = alt((quoted, is_not(" \t\r\n/>")));
+    let attribute = pair(alphanumeric1, opt(preceded(char('='), attr_value)));
+    let attributes = terminated(many0(preceded(multispace1, attribute)), multispace0);
+    map(attributes, |v| {
+        v.into_iter()
+            .map(|(k, v)| (k, v.unwrap_or_default()))
+            .collect()
+    })(input)
+}
+
+fn attr_f32(attrs: &HashMap<&str, &str>, key: &str, default: f32) -> f32 {
+    attrs
+        .get(key)
+        .and_then(|v| v.parse::<f32>().ok())
+        .unwrap_or(default)
+}
+
+fn writing_style_ron(name: &str, attrs: &HashMap<&str, &str>) -> Option<String> {
+    let sec = attr_f32(attrs, "sec", 0.07);
+    let style = match name {
+        "put" => "Put".to_string(),
+        "wipe" => format!("Wipe(sec: {sec:?})"),
+        "fade" => format!("Fade(sec: {sec:?})"),
+        "drop" => format!(
+            "Drop(sec: {sec:?}, height: {:?})",
+            attr_f32(attrs, "height", 16.)
+        ),
+        "pop" => format!(
+            "Pop(sec: {sec:?}, overshoot: {:?})",
+            attr_f32(attrs, "overshoot", 1.7)
+        ),
+        "slide" => format!(
+            "Slide(sec: {sec:?}, distance: {:?})",
+            attr_f32(attrs, "distance", 32.)
+        ),
+        "scramble" => format!(
+            "Scramble(sec: {:?}, interval: {:?})",
+            attr_f32(attrs, "sec", 0.3),
+            attr_f32(attrs, "interval", 0.05)
+        ),
+        _ => return None,
+    };
+    Some(style)
+}
+
+fn text_effect_ron(name: &str, attrs: &HashMap<&str, &str>) -> Option<String> {
+    let effect = match name {
+        "shake" => format!("Shake(amp: {:?})", attr_f32(attrs, "amp", 1.5)),
+        "wave" => format!(
+            "Wave(amp: {:?}, speed: {:?}, length: {:?})",
+            attr_f32(attrs, "amp", 3.),
+            attr_f32(attrs, "speed", 1.),
+            attr_f32(attrs, "length", 160.)
+        ),
+        "rainbow" => format!(
+            "Rainbow(speed: {:?}, length: {:?})",
+            attr_f32(attrs, "speed", 0.5),
+            attr_f32(attrs, "length", 400.)
+        ),
+        _ => return None,
+    };
+    Some(effect)
+}
+
+fn hex_color_ron(hex: &str, alpha: Option<f32>) -> Option<String> {
+    let hex = hex.trim_start_matches('#');
+    let channel = |i: usize| {
+        let v = u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()?;
+        Some(v as f32 / 255.)
+    };
+    let (red, green, blue) = (channel(0)?, channel(2)?, channel(4)?);
+    let alpha = alpha.or_else(|| channel(6)).unwrap_or(1.);
+    Some(format!(
+        "Rgba(red: {red:?}, green: {green:?}, blue: {blue:?}, alpha: {alpha:?})"
+    ))
+}
+
+fn text_style_ron(name: &str, attrs: &HashMap<&str, &str>) -> Option<(&'static str, String)> {
+    let event = match name {
+        "size" => (
+            "FontSizeChange",
+            format!("size: {:?}", attr_f32(attrs, "value", 27.)),
+        ),
+        "color" => {
+            let alpha = attrs.get("alpha").and_then(|v| v.parse::<f32>().ok());
+            let hex = attrs.get("value").copied().unwrap_or_default();
+            let color = hex_color_ron(hex, alpha).or_else(|| hex_color_ron("ffffff", alpha))?;
+            ("FontColorChange", format!("color: {color}"))
+        }
+        "font" => (
+            "FontChange",
+            format!("name: {:?}", attrs.get("name").copied().unwrap_or_default()),
+        ),
+        "reset" => ("ResetTextStyle", String::new()),
+        _ => return None,
+    };
+    Some(event)
+}
+
+type EventRon = fn(&str, &HashMap<&str, &str>) -> Option<(&'static str, String)>;
+
+fn span_tag<'a>(to_ron: EventRon) -> impl FnMut(&'a str) -> IResult<&'a str, ParsedOrder> {
+    move |input: &'a str| {
+        let open_tag = map_opt(
+            delimited(char('<'), pair(alpha1, tag_attributes), char('>')),
+            |(name, attrs)| to_ron(name, &attrs).map(|(e, body)| event_order(e, &body)),
+        );
+        let close_tag = map_opt(delimited(tag("</"), alpha1, char('>')), |name| {
+            to_ron(name, &HashMap::new()).map(|_| event_order("PopTextStyle", ""))
+        });
+        alt((open_tag, close_tag))(input)
+    }
+}
+
+fn writing_tag(input: &str) -> IResult<&str, ParsedOrder> {
+    span_tag(|name, attrs| {
+        writing_style_ron(name, attrs).map(|w| ("PushWritingStyle", format!("writing: {w}")))
+    })(input)
+}
+
+fn effect_tag(input: &str) -> IResult<&str, ParsedOrder> {
+    span_tag(|name, attrs| {
+        text_effect_ron(name, attrs).map(|e| ("PushTextEffect", format!("effect: {e}")))
+    })(input)
+}
+
+fn text_style_tag(input: &str) -> IResult<&str, ParsedOrder> {
+    span_tag(text_style_ron)(input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const HELLO: &[Order] = &[
-        Order::Type { character: 'こ' },
-        Order::Type { character: 'ん' },
-        Order::Type { character: 'に' },
-        Order::Type { character: 'ち' },
-        Order::Type { character: 'は' },
-        Order::CarriageReturn,
-        Order::Type { character: 'は' },
-        Order::Type { character: 'じ' },
-        Order::Type { character: 'め' },
-        Order::Type { character: 'ま' },
-        Order::Type { character: 'し' },
-        Order::Type { character: 'て' },
-    ];
-
-    const ILL: &[Order] = &[
-        Order::Type { character: 'こ' },
-        Order::Type { character: 'の' },
-        Order::Type { character: '家' },
-        Order::Type { character: 'の' },
-        Order::Type { character: '主' },
-        Order::Type { character: '人' },
-        Order::Type { character: 'は' },
-        Order::Type { character: '病' },
-        Order::Type { character: '気' },
-        Order::Type { character: 'で' },
-        Order::Type { character: 'す' },
-    ];
+    fn typed(text: &str) -> Vec<Order> {
+        text.chars()
+            .map(|c| Order::Type {
+                character: c.to_string(),
+            })
+            .collect()
+    }
+
+    fn hello() -> Vec<Order> {
+        [typed("こんにちは"), vec![Order::CarriageReturn], typed("はじめまして")].concat()
+    }
+
+    fn ill() -> Vec<Order> {
+        typed("この家の主人は病気です")
+    }
 
     #[test]
     fn test_hello_br() {
-        let hello_po = HELLO
-            .iter()
-            .map(|o| ParsedOrder::OrderWrapper(o.clone()))
+        let hello_po = hello()
+            .into_iter()
+            .map(ParsedOrder::OrderWrapper)
             .collect::<Vec<_>>();
         assert_eq!(parse_bms("こんにちは<br />はじめまして"), hello_po);
     }
 
     #[test]
     fn test_hello_double_space_end() {
-        let hello_vec = HELLO.into();
+        let hello_vec = hello();
         assert_eq!(
             read_bms("こんにちは  \r\nはじめまして"),
             HashMap::from([("".to_string(), hello_vec)])
@@ -202,8 +361,8 @@ mod tests {
     #[test]
     fn test_h1() {
         let sectioned_phrase = HashMap::from([
-            ("".to_string(), HELLO.into()),
-            ("二つ目".to_string(), ILL.into()),
+            ("".to_string(), hello()),
+            ("二つ目".to_string(), ill()),
         ]);
         assert_eq!(
             read_bms("こんにちは<br>はじめまして<h1>二つ目</h1>この家の主人は病気です"),
@@ -214,8 +373,8 @@ mod tests {
     #[test]
     fn test_under_line() {
         let sectioned_phrase = HashMap::from([
-            ("".to_string(), HELLO.into()),
-            ("二つ目".to_string(), ILL.into()),
+            ("".to_string(), hello()),
+            ("二つ目".to_string(), ill()),
         ]);
         let read =
             read_bms("こんにちは<br css='';/>はじめまして\n二つ目\n======\nこの家の主人は病気です");
@@ -225,8 +384,8 @@ mod tests {
     #[test]
     fn test_sharp_head() {
         let sectioned_phrase = HashMap::from([
-            ("".to_string(), HELLO.into()),
-            ("二つ目".to_string(), ILL.into()),
+            ("".to_string(), hello()),
+            ("二つ目".to_string(), ill()),
         ]);
         let read = read_bms("こんにちは    \r\nはじめまして\r\n# 二つ目\r\nこの家の主人は病気です");
         assert_eq!(read, sectioned_phrase);
@@ -234,27 +393,22 @@ mod tests {
     
     #[test]
     fn test_double_endline() {
-        let pf = &[Order::PageFeed];
-        let paged_phrase = [HELLO, pf, ILL].into_iter().map(|x|x.iter()).flatten().map(|x|x.clone());
-        let vec_pp = paged_phrase.collect::<Vec<Order>>();
+        let vec_pp = [hello(), vec![Order::PageFeed], ill()].concat();
         let read = read_bms("こんにちは    \r\nはじめまして\r\n\r\nこの家の主人は病気です");
         assert_eq!(read,  HashMap::from([("".to_string(), vec_pp)]));
     }
     
     #[test]
     fn test_end_p_tag() {
-        let pf = &[Order::PageFeed];
-        let paged_phrase = [HELLO, pf, ILL].into_iter().map(|x|x.iter()).flatten().map(|x|x.clone());
-        let vec_pp = paged_phrase.collect::<Vec<Order>>();
+        let vec_pp = [hello(), vec![Order::PageFeed], ill()].concat();
         let read = read_bms("<p>こんにちは    \r\nはじめまして</p>この家の主人は病気です");
         assert_eq!(read,  HashMap::from([("".to_string(), vec_pp)]));
     }
 
     #[test]
     fn test_script_tag() {
-        let script = &[Order::ThroghEvent { ron: "test".to_string() }];
-        let with_script = [HELLO, script, ILL].into_iter().map(|x|x.iter()).flatten().map(|x|x.clone());
-        let vec_ws = with_script.collect::<Vec<Order>>();
+        let script = vec![Order::ThroghEvent { ron: "test".to_string() }];
+        let vec_ws = [hello(), script, ill()].concat();
         let read = read_bms("こんにちは    \r\nはじめまして<script>test</script>この家の主人は病気です");
         assert_eq!(read,  HashMap::from([("".to_string(), vec_ws)]));
     }
@@ -262,17 +416,138 @@ mod tests {
     #[test]
     fn test_useless_tag(){
         let useless_taged = vec![
-            ParsedOrder::OrderWrapper(Order::Type { character: 'a' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'a' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'b' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'c' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'd' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: '<' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'a' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: 'b' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: '\\' }),
-            ParsedOrder::OrderWrapper(Order::Type { character: '>' }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "a".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "a".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "b".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "c".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "d".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "<".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "a".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "b".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: "\\".to_string() }),
+            ParsedOrder::OrderWrapper(Order::Type { character: ">".to_string() }),
         ];
         assert_eq!(parse_bms("a<abc>abcd\\<ab\\\\>"), useless_taged);
     }
+
+    #[test]
+    fn test_grapheme_cluster() {
+        let clusters = ["👨\u{200D}👩\u{200D}👧", "e\u{301}", "🇯🇵", "👍🏽", "🦊"]
+            .into_iter()
+            .map(|g| {
+                ParsedOrder::OrderWrapper(Order::Type {
+                    character: g.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parse_bms("👨\u{200D}👩\u{200D}👧e\u{301}🇯🇵👍🏽🦊"),
+            clusters
+        );
+    }
+
+    #[test]
+    fn test_writing_tag() {
+        let push = ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: r#"{"bevy_message_window::message_window::bms_event::PushWritingStyle": (writing: Fade(sec: 0.5))}"#.to_string(),
+        });
+        let pop = ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: r#"{"bevy_message_window::message_window::bms_event::PopTextStyle": ()}"#
+                .to_string(),
+        });
+        let tagged = vec![
+            ParsedOrder::OrderWrapper(Order::Type { character: "a".to_string() }),
+            push,
+            ParsedOrder::OrderWrapper(Order::Type { character: "b".to_string() }),
+            pop,
+        ];
+        assert_eq!(parse_bms("a<fade sec=\"0.5\">b</fade>"), tagged);
+    }
+
+    #[test]
+    fn test_writing_tag_default() {
+        let read = parse_bms("<slide distance=8><pop>");
+        let rons = read
+            .into_iter()
+            .map(|p| match p {
+                ParsedOrder::OrderWrapper(Order::ThroghEvent { ron: r }) => r,
+                _ => String::new(),
+            })
+            .collect::<Vec<_>>();
+        assert!(rons[0].ends_with("(writing: Slide(sec: 0.07, distance: 8.0))}"));
+        assert!(rons[1].ends_with("(writing: Pop(sec: 0.07, overshoot: 1.7))}"));
+    }
+
+    #[test]
+    fn test_soft_line_break() {
+        let hello_vec = hello();
+        assert_eq!(
+            read_bms("こんにちは  \nはじめ\nまして\n"),
+            HashMap::from([("".to_string(), hello_vec)])
+        );
+    }
+
+    #[test]
+    fn test_effect_tag() {
+        let read = parse_bms("<wave amp=4>a</wave>");
+        let push = ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: r#"{"bevy_message_window::message_window::bms_event::PushTextEffect": (effect: Wave(amp: 4.0, speed: 1.0, length: 160.0))}"#.to_string(),
+        });
+        let pop = ParsedOrder::OrderWrapper(Order::ThroghEvent {
+            ron: r#"{"bevy_message_window::message_window::bms_event::PopTextStyle": ()}"#
+                .to_string(),
+        });
+        let tagged = vec![
+            push,
+            ParsedOrder::OrderWrapper(Order::Type { character: "a".to_string() }),
+            pop,
+        ];
+        assert_eq!(read, tagged);
+    }
+
+    #[test]
+    fn test_text_style_tag() {
+        let read = parse_bms("<color value=\"#ff000080\"><size value=35>a</size></color>");
+        let rons = read
+            .into_iter()
+            .map(|p| match p {
+                ParsedOrder::OrderWrapper(Order::ThroghEvent { ron: r }) => r,
+                _ => String::new(),
+            })
+            .collect::<Vec<_>>();
+        assert!(rons[0].ends_with(
+            "FontColorChange\": (color: Rgba(red: 1.0, green: 0.0, blue: 0.0, alpha: 0.5019608))}"
+        ));
+        assert!(rons[1].ends_with("FontSizeChange\": (size: 35.0)}"));
+        assert!(rons[3].ends_with("PopTextStyle\": ()}"));
+        assert!(rons[4].ends_with("PopTextStyle\": ()}"));
+    }
+
+    #[test]
+    fn test_inline_image() {
+        let image = ParsedOrder::OrderWrapper(Order::Image {
+            path: "icons/key.png".to_string(),
+        });
+        let a = ParsedOrder::OrderWrapper(Order::Type { character: "a".to_string() });
+        assert_eq!(
+            parse_bms("a![key](icons/key.png)a"),
+            vec![a.clone(), image.clone(), a.clone()]
+        );
+        assert_eq!(
+            parse_bms("a<img src=\"icons/key.png\" alt=key />a"),
+            vec![a.clone(), image.clone(), a.clone()]
+        );
+        assert_eq!(parse_bms("<img src='icons/key.png'>"), vec![image]);
+        assert_eq!(parse_bms("<img alt=key />a"), vec![a]);
+    }
+
+    #[test]
+    fn test_button_prompt() {
+        let prompt = ParsedOrder::OrderWrapper(Order::ButtonPrompt {
+            action: "advance".to_string(),
+        });
+        assert_eq!(parse_bms("<button action=\"advance\"/>"), vec![prompt.clone()]);
+        assert_eq!(parse_bms("<button action=advance></button>"), vec![prompt]);
+        assert_eq!(parse_bms("<button/>"), vec![]);
+    }
 }
@@ -1,39 +1,148 @@
-use ab_glyph::Font as AFont;
-use bevy::prelude::*;
+use ab_glyph::{Font as AFont, PxScale, ScaleFont};
+use bevy::{asset::HandleId, prelude::*};
+use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::read_script::script_characters;
 use rand::{distributions::uniform::SampleRange, Rng};
 
-#[allow(dead_code)]
 pub fn get_random<T, R: AsRef<[T]>>(list: &R) -> Option<&T> {
     let list_ref: &[T] = list.as_ref();
+    if list_ref.is_empty() {
+        return None;
+    }
     list_ref.get(rand::thread_rng().gen_range(0..list_ref.len()))
 }
 
+// フォントごとに持っている文字の一覧は読み込み時に一度だけ作っておく。
+// フォールバックの選択結果も、フォントの並びと書記素の組で覚えておく。
+#[derive(Resource, Default, Debug)]
+pub struct GlyphCoverage {
+    covered: HashMap<HandleId, HashSet<char>>,
+    choices: HashMap<(u64, String), Handle<Font>>,
+}
+
+impl GlyphCoverage {
+    pub fn insert_font(&mut self, id: HandleId, font: &Font) {
+        let covered = font
+            .font
+            .codepoint_ids()
+            .filter(|(glyph_id, _)| {
+                font.font.outline(*glyph_id).is_some()
+                    || font.font.glyph_raster_image2(*glyph_id, 1).is_some()
+            })
+            .map(|(_, c)| c)
+            .collect();
+        self.covered.insert(id, covered);
+        self.choices.clear();
+    }
+
+    pub fn remove_font(&mut self, id: HandleId) {
+        self.covered.remove(&id);
+        self.choices.clear();
+    }
+
+    pub fn is_loaded(&self, font: &Handle<Font>) -> bool {
+        self.covered.contains_key(&font.id())
+    }
+
+    pub fn covers(&self, font: &Handle<Font>, target: char) -> bool {
+        self.covered
+            .get(&font.id())
+            .is_some_and(|c| c.contains(&target))
+    }
+
+    // ZWJや異体字セレクタはグリフを持たないフォントも多いので判定から外す。
+    pub fn covers_cluster(&self, font: &Handle<Font>, target: &str) -> bool {
+        target
+            .chars()
+            .filter(|c| !is_joiner(*c))
+            .all(|c| self.covers(font, c))
+    }
+
+    pub fn missing_chars<I: IntoIterator<Item = char>>(
+        &self,
+        fonts: &[Handle<Font>],
+        text: I,
+    ) -> Vec<char> {
+        let mut missing = text
+            .into_iter()
+            .filter(|c| !c.is_whitespace() && !is_joiner(*c))
+            .filter(|c| !fonts.iter().any(|f| self.covers(f, *c)))
+            .collect::<Vec<char>>();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    pub fn missing_in_script<S: AsRef<str>>(&self, fonts: &[Handle<Font>], script: S) -> Vec<char> {
+        self.missing_chars(fonts, script_characters(script))
+    }
+}
+
+pub fn update_glyph_coverage(
+    mut events: EventReader<AssetEvent<Font>>,
+    fonts: Res<Assets<Font>>,
+    mut coverage: ResMut<GlyphCoverage>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(font) = fonts.get(handle) {
+                    coverage.insert_font(handle.id(), font);
+                }
+            }
+            AssetEvent::Removed { handle } => coverage.remove_font(handle.id()),
+        }
+    }
+}
+
 pub fn choice_font<R: AsRef<[Handle<Font>]>>(
     list: &R,
-    target: char,
-    fonts: &Assets<Font>,
+    target: &str,
+    coverage: &mut GlyphCoverage,
 ) -> Option<Handle<Font>> {
+    let list = list.as_ref();
+    let mut hasher = DefaultHasher::new();
+    list.iter().for_each(|h| h.id().hash(&mut hasher));
+    let key = (hasher.finish(), target.to_string());
+    if let Some(cached) = coverage.choices.get(&key) {
+        return Some(cached.clone());
+    }
     let finded = list
-        .as_ref()
         .iter()
-        .find(|h| {
-            fonts
-                .get(h)
-                .map(|f| glyph_exists_in_font(f.clone(), target))
-                .unwrap_or(false)
-        })
+        .find(|h| coverage.covers_cluster(h, target))
         .cloned();
-    finded.or(list.as_ref().iter().last().cloned())
+    let choice = finded.or(list.last().cloned());
+    if let Some(font) = choice
+        .as_ref()
+        .filter(|_| list.iter().all(|h| coverage.is_loaded(h)))
+    {
+        coverage.choices.insert(key, font.clone());
+    }
+    choice
+}
+
+pub fn glyph_advance(font: &Handle<Font>, target: &str, size: f32, fonts: &Assets<Font>) -> f32 {
+    fonts
+        .get(font)
+        .map(|f| {
+            let scaled = f.font.as_scaled(PxScale::from(size));
+            target
+                .chars()
+                .map(|c| scaled.h_advance(f.font.glyph_id(c)))
+                .sum()
+        })
+        .unwrap_or(size)
 }
 
-fn glyph_exists_in_font(font: Font, target: char) -> bool {
-    let font_id = font.font.glyph_id(target);
-    let outline = font.font.outline(font_id);
-    let raster = font.font.glyph_raster_image(font_id, 1);
-    outline.is_some() || raster.is_some()
+pub fn is_joiner(target: char) -> bool {
+    matches!(
+        target,
+        '\u{200D}' | '\u{FE00}'..='\u{FE0F}' | '\u{E0020}'..='\u{E007F}'
+    )
 }
 
-#[allow(dead_code)]
 pub fn random_char() -> Option<char> {
     fn range_to_char<R: SampleRange<u32>>(range: R) -> Option<char> {
         std::char::from_u32(rand::thread_rng().gen_range(range))
@@ -47,3 +156,17 @@ pub fn random_char() -> Option<char> {
     let mixed = &[_kanji, hiragana, alphabet_large, alphabet_small];
     get_random(mixed).copied()
 }
+
+pub fn is_punctuation(target: char) -> bool {
+    target.is_ascii_punctuation()
+        || matches!(
+            target,
+            '\u{2010}'..='\u{2027}'
+                | '\u{3000}'..='\u{303F}'
+                | '\u{30FB}'
+                | '\u{FF01}'..='\u{FF0F}'
+                | '\u{FF1A}'..='\u{FF20}'
+                | '\u{FF3B}'..='\u{FF40}'
+                | '\u{FF5B}'..='\u{FF65}'
+        )
+}